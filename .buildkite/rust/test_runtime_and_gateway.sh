@@ -36,3 +36,4 @@ cargo test \
     -p oasis-ethwasi-runtime-common \
     -p oasis-ethwasi-runtime-keymanager \
     -p web3-gateway
+cargo test --features test,prefetch -p oasis-ethwasi-runtime
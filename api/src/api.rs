@@ -3,8 +3,6 @@ use oasis_core_runtime::runtime_api;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-// used in runtime_api! macro
-#[allow(unused_imports)]
 use serde_bytes::ByteBuf;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -30,6 +28,47 @@ pub struct ExecutionResult {
     pub output: Vec<u8>,
 }
 
+/// Storage slots of an account that a transaction is expected to access.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<H256>,
+}
+
+/// Arguments of the transaction method.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TransactionArgs {
+    /// Raw Ethereum transaction bytes.
+    Raw(ByteBuf),
+    /// Raw Ethereum transaction bytes together with an access list. The
+    /// access list is only used to prefetch storage and does not affect
+    /// the execution of the transaction.
+    WithAccessList {
+        #[serde(with = "serde_bytes")]
+        transaction: Vec<u8>,
+        access_list: Vec<AccessListItem>,
+    },
+}
+
+impl TransactionArgs {
+    /// Raw Ethereum transaction bytes.
+    pub fn raw(&self) -> &[u8] {
+        match self {
+            TransactionArgs::Raw(raw) => &raw[..],
+            TransactionArgs::WithAccessList { transaction, .. } => &transaction[..],
+        }
+    }
+
+    /// Access list submitted with the transaction, if any.
+    pub fn access_list(&self) -> &[AccessListItem] {
+        match self {
+            TransactionArgs::Raw(_) => &[],
+            TransactionArgs::WithAccessList { access_list, .. } => access_list,
+        }
+    }
+}
+
 /// Ethereum transaction error.
 #[derive(Debug, Error)]
 pub enum TransactionError {
@@ -45,11 +84,23 @@ pub enum TransactionError {
     TooMuchGas,
     #[error("invalid chain id")]
     InvalidChainId,
+    #[error("transaction too large: {size} bytes (limit {limit})")]
+    TooLarge { size: usize, limit: usize },
+    #[error("contract init code too large: {size} bytes (limit {limit})")]
+    InitCodeTooLarge { size: usize, limit: usize },
+    #[error("batch payload limit reached")]
+    BatchPayloadLimitReached,
+    #[error("invalid ewasm module: {message}")]
+    InvalidWasmModule { message: String },
+    #[error("invalid nonce: expected {expected}, got {got}")]
+    InvalidNonce { expected: U256, got: U256 },
+    #[error("insufficient funds: cost {cost}, balance {balance}")]
+    InsufficientFunds { cost: U256, balance: U256 },
 }
 
 /// Name of the method which executes a transaction.
 pub const METHOD_TX: &'static str = "tx";
 
 runtime_api! {
-    pub fn tx(ByteBuf) -> ExecutionResult;
+    pub fn tx(TransactionArgs) -> ExecutionResult;
 }
@@ -6,8 +6,46 @@ use oasis_core_runtime::{
     TxnDispatcher,
 };
 use oasis_ethwasi_runtime::dispatcher::Dispatcher;
+use oasis_ethwasi_runtime_common::{
+    parity::{DEFAULT_ACCOUNT_CACHE_SIZE, DEFAULT_CODE_CACHE_SIZE},
+    SizeLimits,
+};
 use oasis_ethwasi_runtime_keymanager::trusted_policy_signers;
 
+/// Parses a size set at build time, falling back to the default.
+fn size_limit(name: &str, value: Option<&str>, default: usize) -> usize {
+    value
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid {}: {}", name, value))
+        })
+        .unwrap_or(default)
+}
+
+/// Transaction and batch size limits. All compute nodes must enforce the same
+/// limits, so they are fixed when the runtime is built.
+fn size_limits() -> SizeLimits {
+    let defaults = SizeLimits::default();
+    SizeLimits {
+        max_tx_size: size_limit(
+            "OASIS_RUNTIME_MAX_TX_SIZE",
+            option_env!("OASIS_RUNTIME_MAX_TX_SIZE"),
+            defaults.max_tx_size,
+        ),
+        max_init_code_size: size_limit(
+            "OASIS_RUNTIME_MAX_INIT_CODE_SIZE",
+            option_env!("OASIS_RUNTIME_MAX_INIT_CODE_SIZE"),
+            defaults.max_init_code_size,
+        ),
+        max_batch_size: size_limit(
+            "OASIS_RUNTIME_MAX_BATCH_SIZE",
+            option_env!("OASIS_RUNTIME_MAX_BATCH_SIZE"),
+            defaults.max_batch_size,
+        ),
+    }
+}
+
 fn main() {
     // Initializer.
     let init = |protocol: &Arc<Protocol>,
@@ -34,7 +72,21 @@ fn main() {
                 .expect("failed to update km client policy");
         })));
 
-        Some(Box::new(Dispatcher::new(initializer_km_client)))
+        let mut dispatcher = Dispatcher::new(initializer_km_client);
+        dispatcher.set_size_limits(size_limits());
+        dispatcher.set_state_cache_sizes(
+            size_limit(
+                "OASIS_RUNTIME_ACCOUNT_CACHE_SIZE",
+                option_env!("OASIS_RUNTIME_ACCOUNT_CACHE_SIZE"),
+                DEFAULT_ACCOUNT_CACHE_SIZE,
+            ),
+            size_limit(
+                "OASIS_RUNTIME_CODE_CACHE_SIZE",
+                option_env!("OASIS_RUNTIME_CODE_CACHE_SIZE"),
+                DEFAULT_CODE_CACHE_SIZE,
+            ),
+        );
+        Some(Box::new(dispatcher))
     };
 
     // Start the runtime.
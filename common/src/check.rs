@@ -0,0 +1,179 @@
+//! Transaction checks shared by the runtime and the gateway.
+use anyhow::Result;
+use ethcore::{
+    rlp,
+    transaction::{Action, SignedTransaction, UnverifiedTransaction},
+};
+use ethereum_types::U256;
+use oasis_ethwasi_runtime_api::TransactionError;
+
+use super::{genesis, header::OasisHeader, wasm, SizeLimits, BLOCK_GAS_LIMIT, MIN_GAS_PRICE_GWEI};
+
+/// Decodes a raw transaction and checks it against the rules which do not
+/// depend on any state.
+pub fn tx(raw: &[u8], limits: &SizeLimits) -> Result<SignedTransaction> {
+    // Check the transaction size before decoding it. A transaction larger
+    // than the batch payload limit would never fit into a batch.
+    let max_tx_size = limits.max_tx_size.min(limits.max_batch_size);
+    if raw.len() > max_tx_size {
+        return Err(TransactionError::TooLarge {
+            size: raw.len(),
+            limit: max_tx_size,
+        }
+        .into());
+    }
+
+    let decoded: UnverifiedTransaction = rlp::decode(raw)?;
+
+    // Check that gas < block gas limit.
+    if decoded.as_unsigned().gas > BLOCK_GAS_LIMIT.into() {
+        return Err(TransactionError::TooMuchGas.into());
+    }
+
+    // Check the size of the contract init code.
+    if let Action::Create = decoded.as_unsigned().action {
+        let size = decoded.as_unsigned().data.len();
+        if size > limits.max_init_code_size {
+            return Err(TransactionError::InitCodeTooLarge {
+                size,
+                limit: limits.max_init_code_size,
+            }
+            .into());
+        }
+
+        // Check that ewasm modules follow the contract interface. Invalid
+        // headers are left for the VM to reject.
+        let code = &decoded.as_unsigned().data;
+        let code = OasisHeader::split(code).map_or(&code[..], |(_, body)| body);
+        if wasm::is_wasm(code) {
+            wasm::validate(code).map_err(|err| TransactionError::InvalidWasmModule {
+                message: err.to_string(),
+            })?;
+        }
+    }
+
+    // Check signature.
+    let signed = SignedTransaction::new(decoded)?;
+
+    // Check gas price.
+    if signed.gas_price < MIN_GAS_PRICE_GWEI.into() {
+        return Err(TransactionError::GasPrice.into());
+    }
+
+    // Verify chain id
+    match signed.verify_basic(
+        true,
+        Some(genesis::SPEC.engine.machine().params().chain_id),
+        false,
+    ) {
+        Ok(_) => {}
+        Err(_) => return Err(TransactionError::InvalidChainId.into()),
+    }
+
+    Ok(signed)
+}
+
+/// Checks a transaction against the account of its sender, given the
+/// sender's current `nonce` and `balance`.
+///
+/// Only nonces which were already used are rejected. Later nonces are
+/// accepted, as transactions may be sent while earlier ones of the same
+/// sender are still pending, and gaps are left for the runtime to handle.
+pub fn account(
+    signed: &SignedTransaction,
+    nonce: U256,
+    balance: U256,
+) -> Result<(), TransactionError> {
+    if signed.nonce < nonce {
+        return Err(TransactionError::InvalidNonce {
+            expected: nonce,
+            got: signed.nonce,
+        });
+    }
+
+    let cost = signed
+        .gas
+        .saturating_mul(signed.gas_price)
+        .saturating_add(signed.value);
+    if cost > balance {
+        return Err(TransactionError::InsufficientFunds { cost, balance });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ethcore::transaction::Transaction;
+    use ethereum_types::Address;
+
+    use super::*;
+
+    fn transaction(nonce: u64, value: u64) -> SignedTransaction {
+        Transaction {
+            nonce: nonce.into(),
+            gas_price: 1.into(),
+            gas: 21000.into(),
+            action: Action::Call(Address::zero()),
+            value: value.into(),
+            data: vec![],
+        }
+        .fake_sign(Address::from(1))
+    }
+
+    #[test]
+    fn test_tx_too_large() {
+        let limits = SizeLimits {
+            max_tx_size: 16,
+            ..Default::default()
+        };
+        let err = tx(&[0; 32], &limits).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TransactionError>(),
+            Some(&TransactionError::TooLarge {
+                size: 32,
+                limit: 16
+            })
+        ));
+
+        // Transactions must also fit into a batch.
+        let limits = SizeLimits {
+            max_batch_size: 8,
+            ..Default::default()
+        };
+        let err = tx(&[0; 32], &limits).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TransactionError>(),
+            Some(&TransactionError::TooLarge { size: 32, limit: 8 })
+        ));
+    }
+
+    #[test]
+    fn test_account_nonce() {
+        let balance = U256::from(1_000_000);
+        assert!(account(&transaction(5, 0), 5.into(), balance).is_ok());
+        // Transactions may be queued behind pending ones.
+        assert!(account(&transaction(6, 0), 5.into(), balance).is_ok());
+        assert!(account(&transaction(8, 0), 5.into(), balance).is_ok());
+
+        assert!(matches!(
+            account(&transaction(4, 0), 5.into(), balance),
+            Err(TransactionError::InvalidNonce { .. })
+        ));
+    }
+
+    #[test]
+    fn test_account_balance() {
+        // 21000 gas at a gas price of 1.
+        assert!(account(&transaction(0, 0), 0.into(), 21000.into()).is_ok());
+        assert!(account(&transaction(0, 1), 0.into(), 21001.into()).is_ok());
+
+        match account(&transaction(0, 1), 0.into(), 21000.into()) {
+            Err(TransactionError::InsufficientFunds { cost, balance }) => {
+                assert_eq!(cost, 21001.into());
+                assert_eq!(balance, 21000.into());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Oasis contract header.
+//!
+//! Contract code may be prefixed with a header on deployment, which consists
+//! of `OASIS_HEADER_PREFIX`, a big-endian `u16` version, a big-endian `u16`
+//! length and that many bytes of JSON contents.
+use serde::{Deserialize, Serialize};
+use serde_json;
+use thiserror::Error;
+use vm::OASIS_HEADER_PREFIX;
+
+/// Latest supported header version.
+pub const HEADER_VERSION: u16 = 1;
+
+/// Size of the version and length fields following the prefix.
+const FIELDS_SIZE: usize = 4;
+
+/// Oasis header error.
+#[derive(Debug, Error)]
+pub enum HeaderError {
+    #[error("truncated header")]
+    Truncated,
+    #[error("unsupported header version: {0}")]
+    UnsupportedVersion(u16),
+    #[error("invalid header contents: {0}")]
+    InvalidContents(#[from] serde_json::Error),
+}
+
+/// JSON contents of the header.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Contents {
+    #[serde(default, skip_serializing_if = "is_false")]
+    confidential: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expiry: Option<u64>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Oasis contract header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OasisHeader {
+    /// Header version.
+    pub version: u16,
+    /// Whether the contract is confidential.
+    pub confidential: bool,
+    /// Expiration timestamp of the contract (seconds since the epoch).
+    pub expiry: Option<u64>,
+}
+
+impl Default for OasisHeader {
+    fn default() -> Self {
+        OasisHeader {
+            version: HEADER_VERSION,
+            confidential: false,
+            expiry: None,
+        }
+    }
+}
+
+impl OasisHeader {
+    /// Returns a builder for a header of the latest version.
+    pub fn builder() -> OasisHeaderBuilder {
+        OasisHeaderBuilder::default()
+    }
+
+    /// Encodes the header, including the prefix.
+    pub fn encode(&self) -> Vec<u8> {
+        let contents = serde_json::to_vec(&Contents {
+            confidential: self.confidential,
+            expiry: self.expiry,
+        })
+        .expect("header contents must serialize");
+
+        let mut data = OASIS_HEADER_PREFIX.to_vec();
+        data.extend_from_slice(&self.version.to_be_bytes());
+        data.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        data.extend_from_slice(&contents);
+        data
+    }
+
+    /// Prefixes contract code with the encoded header.
+    pub fn prepend_to(&self, code: &[u8]) -> Vec<u8> {
+        let mut data = self.encode();
+        data.extend_from_slice(code);
+        data
+    }
+
+    /// Splits the header off contract code.
+    ///
+    /// Returns `None` and the unchanged code if the code has no header.
+    pub fn split(code: &[u8]) -> Result<(Option<OasisHeader>, &[u8]), HeaderError> {
+        if !code.starts_with(&OASIS_HEADER_PREFIX[..]) {
+            return Ok((None, code));
+        }
+
+        let code = &code[OASIS_HEADER_PREFIX.len()..];
+        if code.len() < FIELDS_SIZE {
+            return Err(HeaderError::Truncated);
+        }
+        let version = u16::from_be_bytes([code[0], code[1]]);
+        if version == 0 || version > HEADER_VERSION {
+            return Err(HeaderError::UnsupportedVersion(version));
+        }
+        let length = u16::from_be_bytes([code[2], code[3]]) as usize;
+        let code = &code[FIELDS_SIZE..];
+        if code.len() < length {
+            return Err(HeaderError::Truncated);
+        }
+
+        let contents: Contents = serde_json::from_slice(&code[..length])?;
+        let header = OasisHeader {
+            version,
+            confidential: contents.confidential,
+            expiry: contents.expiry,
+        };
+        Ok((Some(header), &code[length..]))
+    }
+}
+
+/// Builder for an `OasisHeader`.
+#[derive(Default)]
+pub struct OasisHeaderBuilder {
+    header: OasisHeader,
+}
+
+impl OasisHeaderBuilder {
+    /// Marks the contract as confidential.
+    pub fn confidential(mut self, confidential: bool) -> Self {
+        self.header.confidential = confidential;
+        self
+    }
+
+    /// Sets the expiration timestamp of the contract.
+    pub fn expiry(mut self, expiry: u64) -> Self {
+        self.header.expiry = Some(expiry);
+        self
+    }
+
+    /// Returns the built header.
+    pub fn build(self) -> OasisHeader {
+        self.header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let header = OasisHeader::builder()
+            .confidential(true)
+            .expiry(1577836800)
+            .build();
+        let data = header.prepend_to(b"code");
+
+        let (decoded, code) = OasisHeader::split(&data).unwrap();
+        assert_eq!(decoded, Some(header));
+        assert_eq!(code, b"code");
+    }
+
+    #[test]
+    fn test_encoding() {
+        let data = OasisHeader::builder().expiry(10).build().encode();
+        let contents = br#"{"expiry":10}"#;
+
+        let mut expected = OASIS_HEADER_PREFIX.to_vec();
+        expected.extend_from_slice(&[0, 1, 0, contents.len() as u8]);
+        expected.extend_from_slice(contents);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_no_header() {
+        let (header, code) = OasisHeader::split(b"code").unwrap();
+        assert_eq!(header, None);
+        assert_eq!(code, b"code");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let data = OasisHeader::default().prepend_to(b"code");
+        let prefix = OASIS_HEADER_PREFIX.len();
+
+        // Truncated fields and contents.
+        assert!(matches!(
+            OasisHeader::split(&data[..prefix + 2]),
+            Err(HeaderError::Truncated)
+        ));
+        assert!(matches!(
+            OasisHeader::split(&data[..prefix + FIELDS_SIZE + 1]),
+            Err(HeaderError::Truncated)
+        ));
+
+        // Unsupported version.
+        let mut unsupported = data.clone();
+        unsupported[prefix + 1] = 2;
+        assert!(matches!(
+            OasisHeader::split(&unsupported),
+            Err(HeaderError::UnsupportedVersion(2))
+        ));
+
+        // Unknown field.
+        let mut data = OASIS_HEADER_PREFIX.to_vec();
+        let contents = br#"{"unknown":true}"#;
+        data.extend_from_slice(&[0, 1, 0, contents.len() as u8]);
+        data.extend_from_slice(contents);
+        assert!(matches!(
+            OasisHeader::split(&data),
+            Err(HeaderError::InvalidContents(_))
+        ));
+    }
+}
@@ -7,20 +7,58 @@ extern crate hashdb;
 extern crate io_context;
 extern crate keccak_hash;
 extern crate lazy_static;
+extern crate lru_cache;
 extern crate oasis_core_keymanager_client;
 extern crate oasis_core_runtime;
+extern crate oasis_ethwasi_runtime_api;
+extern crate parity_wasm;
+extern crate prometheus;
+extern crate serde;
+extern crate serde_json;
+extern crate thiserror;
 extern crate vm;
 extern crate zeroize;
 
+pub mod check;
 pub mod confidential;
 pub mod genesis;
+pub mod header;
 pub mod parity;
+pub mod proof;
 pub mod storage;
+pub mod wasm;
 
 /// Block gas limit.
 pub const BLOCK_GAS_LIMIT: usize = 16_000_000;
 /// Minimum gas price (in gwei).
 pub const MIN_GAS_PRICE_GWEI: usize = 1;
+/// Default maximum size of a raw transaction (in bytes).
+pub const MAX_TX_SIZE: usize = 128 * 1024;
+/// Default maximum size of contract init code (in bytes).
+pub const MAX_INIT_CODE_SIZE: usize = 96 * 1024;
+/// Default maximum total size of the transactions in a batch (in bytes).
+pub const MAX_BATCH_SIZE: usize = 2 * 1024 * 1024;
+
+/// Size limits enforced on transactions and batches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// Maximum size of a raw transaction (in bytes).
+    pub max_tx_size: usize,
+    /// Maximum size of contract init code (in bytes).
+    pub max_init_code_size: usize,
+    /// Maximum total size of the transactions in a batch (in bytes).
+    pub max_batch_size: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        SizeLimits {
+            max_tx_size: MAX_TX_SIZE,
+            max_init_code_size: MAX_INIT_CODE_SIZE,
+            max_batch_size: MAX_BATCH_SIZE,
+        }
+    }
+}
 
 /// Ethereum transaction hash tag (value is the Ethereum transaction hash).
 pub const TAG_ETH_TX_HASH: &'static [u8] = b"heth";
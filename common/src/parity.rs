@@ -1,9 +1,26 @@
 //! Common parity helpers.
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
 
 use ethcore::{self, state::Account};
 use ethereum_types::{Address, H256};
 use hashdb::HashDB;
+use lazy_static::lazy_static;
+use lru_cache::LruCache;
+use oasis_core_runtime::common::crypto::hash::Hash;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+
+// Metrics.
+lazy_static! {
+    static ref STATE_CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "oasis_runtime_state_cache_lookups",
+        "Number of shared state cache lookups by result",
+        &["cache", "result"]
+    )
+    .unwrap();
+}
 
 /// Null backend for parity state.
 ///
@@ -43,3 +60,347 @@ impl ethcore::state::backend::Backend for NullBackend {
         false
     }
 }
+
+/// Default number of accounts kept in the shared cache.
+pub const DEFAULT_ACCOUNT_CACHE_SIZE: usize = 4096;
+/// Default number of contract codes kept in the shared cache.
+pub const DEFAULT_CODE_CACHE_SIZE: usize = 64;
+
+/// Account and code cache shared across batches.
+///
+/// Code is keyed by its hash and therefore never becomes stale. Accounts are
+/// only valid for the state root they were read at, so the account cache is
+/// tagged with that root and cleared whenever a batch starts from a different
+/// one.
+pub struct StateCache {
+    accounts: Mutex<AccountCache>,
+    code: Mutex<LruCache<H256, Arc<Vec<u8>>>>,
+    account_hits: AtomicU64,
+    account_misses: AtomicU64,
+    code_hits: AtomicU64,
+    code_misses: AtomicU64,
+}
+
+struct AccountCache {
+    /// State root the cached accounts are valid for, `None` if unknown.
+    root: Option<Hash>,
+    accounts: LruCache<Address, Option<Account>>,
+}
+
+/// Cumulative hit/miss counters of a `StateCache`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StateCacheStats {
+    pub account_hits: u64,
+    pub account_misses: u64,
+    pub code_hits: u64,
+    pub code_misses: u64,
+}
+
+impl StateCacheStats {
+    /// Fraction of account lookups served from the cache.
+    pub fn account_hit_rate(&self) -> f64 {
+        hit_rate(self.account_hits, self.account_misses)
+    }
+
+    /// Fraction of code lookups served from the cache.
+    pub fn code_hit_rate(&self) -> f64 {
+        hit_rate(self.code_hits, self.code_misses)
+    }
+}
+
+fn hit_rate(hits: u64, misses: u64) -> f64 {
+    if hits + misses == 0 {
+        return 0.0;
+    }
+    hits as f64 / (hits + misses) as f64
+}
+
+impl StateCache {
+    pub fn new(account_cache_size: usize, code_cache_size: usize) -> Self {
+        Self {
+            accounts: Mutex::new(AccountCache {
+                root: None,
+                accounts: LruCache::new(account_cache_size),
+            }),
+            code: Mutex::new(LruCache::new(code_cache_size)),
+            account_hits: AtomicU64::new(0),
+            account_misses: AtomicU64::new(0),
+            code_hits: AtomicU64::new(0),
+            code_misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Merges the accounts propagated by a finished batch into the cache.
+    ///
+    /// If the batch was committed, the cached accounts are only valid again
+    /// once the resulting state root is known (see `finalize`). Otherwise only
+    /// accounts that were not modified by the batch are kept.
+    pub fn merge(&self, backend: CachedBackend, committed: bool) {
+        let mut inner = self.accounts.lock().unwrap();
+        if inner.root != Some(backend.state_root) {
+            // Another batch started in the meantime, entries may be stale.
+            return;
+        }
+
+        for (address, account, modified) in backend.propagated {
+            if committed || !modified {
+                inner.accounts.insert(address, account);
+            }
+        }
+        if committed {
+            inner.root = None;
+        }
+    }
+
+    /// Marks the accounts merged from a committed batch as valid for the
+    /// given (new) state root.
+    pub fn finalize(&self, new_state_root: Hash) {
+        let mut inner = self.accounts.lock().unwrap();
+        if inner.root.is_none() {
+            inner.root = Some(new_state_root);
+        }
+    }
+
+    /// Returns the cumulative cache statistics.
+    pub fn stats(&self) -> StateCacheStats {
+        StateCacheStats {
+            account_hits: self.account_hits.load(Ordering::Relaxed),
+            account_misses: self.account_misses.load(Ordering::Relaxed),
+            code_hits: self.code_hits.load(Ordering::Relaxed),
+            code_misses: self.code_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, cache: &str, hits: &AtomicU64, misses: &AtomicU64, hit: bool) {
+        let result = if hit {
+            hits.fetch_add(1, Ordering::Relaxed);
+            "hit"
+        } else {
+            misses.fetch_add(1, Ordering::Relaxed);
+            "miss"
+        };
+        STATE_CACHE_LOOKUPS
+            .with_label_values(&[cache, result])
+            .inc();
+    }
+}
+
+/// Parity state backend which reads through a shared `StateCache`.
+///
+/// Accounts propagated by the state when it is dropped are collected and
+/// merged into the shared cache with `StateCache::merge`.
+pub struct CachedBackend {
+    cache: Arc<StateCache>,
+    state_root: Hash,
+    propagated: Vec<(Address, Option<Account>, bool)>,
+}
+
+impl CachedBackend {
+    /// Creates a backend for a batch executed on top of `state_root`.
+    ///
+    /// Cached accounts are dropped unless they were read at (or committed
+    /// into) the same state root.
+    pub fn new(cache: Arc<StateCache>, state_root: Hash) -> Self {
+        {
+            let mut inner = cache.accounts.lock().unwrap();
+            if inner.root != Some(state_root) {
+                inner.accounts.clear();
+                inner.root = Some(state_root);
+            }
+        }
+
+        Self {
+            cache,
+            state_root,
+            propagated: Vec::new(),
+        }
+    }
+}
+
+impl ethcore::state::backend::Backend for CachedBackend {
+    fn as_hashdb(&self) -> &dyn HashDB {
+        unimplemented!("HashDB should never be used");
+    }
+
+    fn as_hashdb_mut(&mut self) -> &mut dyn HashDB {
+        unimplemented!("HashDB should never be used");
+    }
+
+    fn add_to_account_cache(&mut self, addr: Address, data: Option<Account>, modified: bool) {
+        self.propagated.push((addr, data, modified));
+    }
+
+    fn cache_code(&self, hash: H256, code: Arc<Vec<u8>>) {
+        self.cache.code.lock().unwrap().insert(hash, code);
+    }
+
+    fn get_cached_account(&self, addr: &Address) -> Option<Option<Account>> {
+        let result = self
+            .cache
+            .accounts
+            .lock()
+            .unwrap()
+            .accounts
+            .get_mut(addr)
+            .map(|account| account.as_ref().map(Account::clone_basic));
+        self.cache.record(
+            "account",
+            &self.cache.account_hits,
+            &self.cache.account_misses,
+            result.is_some(),
+        );
+        result
+    }
+
+    fn get_cached<F, U>(&self, addr: &Address, f: F) -> Option<U>
+    where
+        F: FnOnce(Option<&mut Account>) -> U,
+    {
+        // The state only uses the closure to read the account and to fill in
+        // its code and storage caches. Changes made by transactions go through
+        // the state's own cache and are merged back in `StateCache::merge`.
+        let result = self
+            .cache
+            .accounts
+            .lock()
+            .unwrap()
+            .accounts
+            .get_mut(addr)
+            .map(|account| f(account.as_mut()));
+        self.cache.record(
+            "account",
+            &self.cache.account_hits,
+            &self.cache.account_misses,
+            result.is_some(),
+        );
+        result
+    }
+
+    fn get_cached_code(&self, hash: &H256) -> Option<Arc<Vec<u8>>> {
+        let result = self.cache.code.lock().unwrap().get_mut(hash).cloned();
+        self.cache.record(
+            "code",
+            &self.cache.code_hits,
+            &self.cache.code_misses,
+            result.is_some(),
+        );
+        result
+    }
+
+    fn note_non_null_account(&self, _: &Address) {}
+
+    fn is_known_null(&self, _: &Address) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethcore::state::backend::Backend;
+    use ethereum_types::U256;
+
+    use super::*;
+
+    fn root(value: u8) -> Hash {
+        Hash::digest_bytes(&[value])
+    }
+
+    #[test]
+    fn test_state_cache_code() {
+        let cache = Arc::new(StateCache::new(16, 16));
+        let backend = CachedBackend::new(cache.clone(), root(0));
+        backend.cache_code(H256::from(1), Arc::new(vec![1, 2, 3]));
+
+        // Code is content-addressed and survives state root changes.
+        let backend = CachedBackend::new(cache.clone(), root(1));
+        assert_eq!(
+            backend.get_cached_code(&H256::from(1)),
+            Some(Arc::new(vec![1, 2, 3]))
+        );
+        assert_eq!(backend.get_cached_code(&H256::from(2)), None);
+        assert_eq!(cache.stats().code_hits, 1);
+        assert_eq!(cache.stats().code_misses, 1);
+    }
+
+    #[test]
+    fn test_state_cache_commit() {
+        let cache = Arc::new(StateCache::new(16, 16));
+        let mut backend = CachedBackend::new(cache.clone(), root(0));
+        backend.add_to_account_cache(
+            Address::from(1),
+            Some(Account::new_basic(U256::from(10), U256::zero())),
+            true,
+        );
+        cache.merge(backend, true);
+
+        // Committed accounts are not served until the new root is known.
+        let backend = CachedBackend::new(cache.clone(), root(0));
+        assert!(backend.get_cached_account(&Address::from(1)).is_none());
+
+        let mut backend = CachedBackend::new(cache.clone(), root(0));
+        backend.add_to_account_cache(
+            Address::from(1),
+            Some(Account::new_basic(U256::from(10), U256::zero())),
+            true,
+        );
+        cache.merge(backend, true);
+        cache.finalize(root(1));
+
+        let backend = CachedBackend::new(cache.clone(), root(1));
+        let account = backend
+            .get_cached_account(&Address::from(1))
+            .expect("account should be cached")
+            .expect("account should exist");
+        assert_eq!(*account.balance(), U256::from(10));
+
+        // A batch on top of a different root must not see the account.
+        let backend = CachedBackend::new(cache.clone(), root(2));
+        assert!(backend.get_cached_account(&Address::from(1)).is_none());
+    }
+
+    #[test]
+    fn test_state_cache_get_cached() {
+        let cache = Arc::new(StateCache::new(16, 16));
+        let mut backend = CachedBackend::new(cache.clone(), root(0));
+        backend.add_to_account_cache(
+            Address::from(1),
+            Some(Account::new_basic(U256::from(10), U256::zero())),
+            false,
+        );
+        cache.merge(backend, false);
+
+        let backend = CachedBackend::new(cache.clone(), root(0));
+        let balance = backend.get_cached(&Address::from(1), |account| {
+            // Code can be read through the backend while the account is borrowed.
+            assert!(backend.get_cached_code(&H256::from(1)).is_none());
+            *account.expect("account should exist").balance()
+        });
+        assert_eq!(balance, Some(U256::from(10)));
+        assert_eq!(backend.get_cached(&Address::from(2), |_| ()), None);
+        assert_eq!(cache.stats().account_hits, 1);
+        assert_eq!(cache.stats().account_misses, 1);
+    }
+
+    #[test]
+    fn test_state_cache_check_only() {
+        let cache = Arc::new(StateCache::new(16, 16));
+        let mut backend = CachedBackend::new(cache.clone(), root(0));
+        backend.add_to_account_cache(Address::from(1), None, false);
+        backend.add_to_account_cache(
+            Address::from(2),
+            Some(Account::new_basic(U256::from(10), U256::zero())),
+            true,
+        );
+        cache.merge(backend, false);
+
+        // Only unmodified accounts are kept from batches which are not committed.
+        let backend = CachedBackend::new(cache.clone(), root(0));
+        assert_eq!(
+            backend
+                .get_cached_account(&Address::from(1))
+                .map(|a| a.is_none()),
+            Some(true)
+        );
+        assert!(backend.get_cached_account(&Address::from(2)).is_none());
+    }
+}
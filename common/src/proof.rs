@@ -0,0 +1,156 @@
+//! Verification of MKVS inclusion proofs.
+//!
+//! Proofs are as returned by the storage `SyncGet` method, so they can be
+//! checked by anyone who trusts a block's state root.
+use std::any::Any;
+
+use anyhow::{anyhow, Result};
+use io_context::Context;
+use oasis_core_runtime::{
+    common::crypto::hash::Hash,
+    storage::mkvs::{
+        sync::{GetPrefixesRequest, GetRequest, IterateRequest, Proof, ProofResponse, ReadSync},
+        Root, RootType, Tree,
+    },
+};
+
+/// Read syncer which serves a single proof.
+///
+/// The tree verifies every proof it receives against the hash of the node it
+/// is resolving, so lookups only succeed if the proof is valid and covers the
+/// whole path to the key.
+struct ProofReadSyncer {
+    proof: Proof,
+}
+
+impl ReadSync for ProofReadSyncer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, _ctx: Context, _request: GetRequest) -> Result<ProofResponse> {
+        Ok(ProofResponse {
+            proof: self.proof.clone(),
+        })
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        _ctx: Context,
+        _request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        Err(anyhow!("proof does not support prefix queries"))
+    }
+
+    fn sync_iterate(&mut self, _ctx: Context, _request: IterateRequest) -> Result<ProofResponse> {
+        Err(anyhow!("proof does not support iteration"))
+    }
+}
+
+/// Verifies an inclusion proof for `key` against `state_root` and returns
+/// the value stored under the key, or `None` if the proof shows that the key
+/// does not exist.
+///
+/// Fails if the proof is invalid or does not cover the key.
+pub fn verify(state_root: Hash, key: &[u8], proof: Proof) -> Result<Option<Vec<u8>>> {
+    if proof.untrusted_root != state_root {
+        return Err(anyhow!("proof is for a different state root"));
+    }
+
+    let tree = Tree::make()
+        .with_root(Root {
+            root_type: RootType::State,
+            hash: state_root,
+            ..Default::default()
+        })
+        .new(Box::new(ProofReadSyncer { proof }));
+
+    tree.get(Context::background(), key)
+        .map_err(|err| anyhow!("invalid proof: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use oasis_core_runtime::storage::{
+        mkvs::{
+            sync::{NoopReadSyncer, TreeID},
+            OverlayTree,
+        },
+        MKVS,
+    };
+
+    use super::*;
+
+    /// Builds a tree with the given entries and returns it with its root.
+    fn populated_tree(entries: &[(&[u8], &[u8])]) -> (Tree, Hash) {
+        let mut tree = Tree::make()
+            .with_root_type(RootType::State)
+            .new(Box::new(NoopReadSyncer {}));
+        let mut overlay = OverlayTree::new(&mut tree);
+        for (key, value) in entries {
+            MKVS::insert(&mut overlay, Context::background(), key, value);
+        }
+        let (_, root) = overlay
+            .commit_both(Context::background(), Default::default(), 0)
+            .expect("commit should succeed");
+        (tree, root)
+    }
+
+    /// Returns the proof for `key` served by the tree, like `SyncGet` does.
+    fn prove(tree: &mut Tree, state_root: Hash, key: &[u8]) -> Proof {
+        let request = GetRequest {
+            tree: TreeID {
+                root: Root {
+                    root_type: RootType::State,
+                    hash: state_root,
+                    ..Default::default()
+                },
+                position: state_root,
+            },
+            key: key.to_vec(),
+            include_siblings: false,
+        };
+        tree.sync_get(Context::background(), request)
+            .expect("proof should be served")
+            .proof
+    }
+
+    #[test]
+    fn test_verify() {
+        let (mut tree, state_root) =
+            populated_tree(&[(b"foo", b"bar"), (b"moo", b"boo"), (b"other", b"value")]);
+
+        let proof = prove(&mut tree, state_root, b"moo");
+        assert_eq!(
+            verify(state_root, b"moo", proof).unwrap(),
+            Some(b"boo".to_vec())
+        );
+
+        // A proof of absence.
+        let proof = prove(&mut tree, state_root, b"missing");
+        assert_eq!(verify(state_root, b"missing", proof).unwrap(), None);
+
+        // The proof of one key does not prove the value of another key.
+        let proof = prove(&mut tree, state_root, b"foo");
+        assert!(verify(state_root, b"other", proof).is_err());
+    }
+
+    #[test]
+    fn test_verify_invalid() {
+        let state_root = Hash::digest_bytes(b"state root");
+
+        // Proof for another root.
+        let proof = Proof {
+            untrusted_root: Hash::digest_bytes(b"other root"),
+            entries: vec![],
+        };
+        assert!(verify(state_root, b"key", proof).is_err());
+
+        // Proof which does not contain the root node.
+        let proof = Proof {
+            untrusted_root: state_root,
+            entries: vec![],
+        };
+        assert!(verify(state_root, b"key", proof).is_err());
+    }
+}
@@ -1,41 +1,81 @@
 //! Storage wrappers.
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
 use anyhow::{anyhow, Result};
-use ethcore;
+use ethcore::{
+    self,
+    state::{MKVS_KEY_CODE, MKVS_KEY_METADATA},
+};
 use io_context::Context;
 use oasis_core_runtime::storage::{KeyValue, StorageContext};
 
+/// Length of the account address prefix of MKVS keys.
+const ADDRESS_LENGTH: usize = 20;
+
 /// MKVS implementation which uses the thread-local MKVS provided by
 /// the `StorageContext`.
 pub struct ThreadLocalMKVS {
     // TODO: The proper way would be to change Parity API to support contexts.
     ctx: Arc<Context>,
+    stats: Option<Arc<StorageStatsRecorder>>,
 }
 
 impl ThreadLocalMKVS {
     pub fn new(ctx: Context) -> Self {
-        Self { ctx: ctx.freeze() }
+        Self {
+            ctx: ctx.freeze(),
+            stats: None,
+        }
+    }
+
+    /// Creates an MKVS which records all I/O into `stats`.
+    pub fn with_stats(ctx: Context, stats: Arc<StorageStatsRecorder>) -> Self {
+        Self {
+            ctx: ctx.freeze(),
+            stats: Some(stats),
+        }
     }
 }
 
 impl ethcore::mkvs::MKVS for ThreadLocalMKVS {
     fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        StorageContext::with_current(|mkvs, _untrusted_local| {
+        let value = StorageContext::with_current(|mkvs, _untrusted_local| {
             mkvs.get(Context::create_child(&self.ctx), key)
-        })
+        });
+        if let Some(ref stats) = self.stats {
+            let kind = stats.record(key, |c| {
+                c.reads += 1;
+                c.read_bytes += value.as_ref().map_or(0, |v| v.len()) as u64;
+            });
+            if kind == KeyKind::Storage {
+                stats.log_storage_key(key);
+            }
+        }
+        value
     }
 
     fn insert(&mut self, key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+        if let Some(ref stats) = self.stats {
+            stats.record(key, |c| {
+                c.writes += 1;
+                c.write_bytes += (key.len() + value.len()) as u64;
+            });
+        }
         StorageContext::with_current(|mkvs, _untrusted_local| {
             mkvs.insert(Context::create_child(&self.ctx), key, value)
         })
     }
 
     fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        if let Some(ref stats) = self.stats {
+            stats.record(key, |c| c.removes += 1);
+        }
         StorageContext::with_current(|mkvs, _untrusted_local| {
             mkvs.remove(Context::create_child(&self.ctx), key)
         })
@@ -44,10 +84,145 @@ impl ethcore::mkvs::MKVS for ThreadLocalMKVS {
     fn boxed_clone(&self) -> Box<dyn ethcore::mkvs::MKVS> {
         Box::new(ThreadLocalMKVS {
             ctx: self.ctx.clone(),
+            stats: self.stats.clone(),
         })
     }
 }
 
+/// Kind of an MKVS key, derived from the suffix following the account address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyKind {
+    Code,
+    Metadata,
+    Storage,
+}
+
+impl KeyKind {
+    fn of(key: &[u8]) -> Self {
+        let suffix = key.get(ADDRESS_LENGTH..).unwrap_or(&[]);
+        if suffix.starts_with(MKVS_KEY_CODE) {
+            KeyKind::Code
+        } else if suffix.starts_with(MKVS_KEY_METADATA) {
+            KeyKind::Metadata
+        } else {
+            KeyKind::Storage
+        }
+    }
+}
+
+/// Storage I/O counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageCounters {
+    pub reads: u64,
+    pub read_bytes: u64,
+    pub writes: u64,
+    pub write_bytes: u64,
+    pub removes: u64,
+}
+
+impl StorageCounters {
+    fn add(&self, other: &Self) -> Self {
+        Self {
+            reads: self.reads + other.reads,
+            read_bytes: self.read_bytes + other.read_bytes,
+            writes: self.writes + other.writes,
+            write_bytes: self.write_bytes + other.write_bytes,
+            removes: self.removes + other.removes,
+        }
+    }
+
+    fn saturating_sub(&self, other: &Self) -> Self {
+        Self {
+            reads: self.reads.saturating_sub(other.reads),
+            read_bytes: self.read_bytes.saturating_sub(other.read_bytes),
+            writes: self.writes.saturating_sub(other.writes),
+            write_bytes: self.write_bytes.saturating_sub(other.write_bytes),
+            removes: self.removes.saturating_sub(other.removes),
+        }
+    }
+}
+
+/// Storage I/O statistics, split by the kind of key accessed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Contract code.
+    pub code: StorageCounters,
+    /// Account metadata (balance, nonce, ...).
+    pub metadata: StorageCounters,
+    /// Contract storage and any other keys.
+    pub storage: StorageCounters,
+}
+
+impl StorageStats {
+    /// Returns the counters summed over all key kinds.
+    pub fn total(&self) -> StorageCounters {
+        self.code.add(&self.metadata).add(&self.storage)
+    }
+
+    /// Returns the I/O performed since the `earlier` snapshot was taken.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            code: self.code.saturating_sub(&earlier.code),
+            metadata: self.metadata.saturating_sub(&earlier.metadata),
+            storage: self.storage.saturating_sub(&earlier.storage),
+        }
+    }
+}
+
+/// Recorder of MKVS I/O statistics, shared by all MKVS handles of a batch.
+#[derive(Default)]
+pub struct StorageStatsRecorder {
+    stats: Mutex<StorageStats>,
+    /// Contract storage keys read so far, if key logging is enabled.
+    storage_keys: Option<Mutex<HashSet<Vec<u8>>>>,
+}
+
+impl StorageStatsRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a recorder which also remembers which contract storage keys
+    /// were read (see `take_storage_keys`).
+    pub fn with_key_log() -> Self {
+        Self {
+            stats: Default::default(),
+            storage_keys: Some(Default::default()),
+        }
+    }
+
+    /// Returns the statistics recorded so far.
+    pub fn snapshot(&self) -> StorageStats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Returns the contract storage keys read since the last call and clears
+    /// the key log.
+    pub fn take_storage_keys(&self) -> HashSet<Vec<u8>> {
+        self.storage_keys
+            .as_ref()
+            .map(|keys| std::mem::replace(&mut *keys.lock().unwrap(), HashSet::new()))
+            .unwrap_or_default()
+    }
+
+    fn record<F: FnOnce(&mut StorageCounters)>(&self, key: &[u8], f: F) -> KeyKind {
+        let mut stats = self.stats.lock().unwrap();
+        let kind = KeyKind::of(key);
+        f(match kind {
+            KeyKind::Code => &mut stats.code,
+            KeyKind::Metadata => &mut stats.metadata,
+            KeyKind::Storage => &mut stats.storage,
+        });
+        kind
+    }
+
+    fn log_storage_key(&self, key: &[u8]) {
+        if let Some(ref keys) = self.storage_keys {
+            keys.lock().unwrap().insert(key.to_vec());
+        }
+    }
+}
+
 /// In-memory trivial key/value storage.
 pub struct MemoryKeyValue(Mutex<HashMap<Vec<u8>, Vec<u8>>>);
 
@@ -72,3 +247,286 @@ impl KeyValue for MemoryKeyValue {
         Ok(())
     }
 }
+
+/// Size of a log record header (key length and value length).
+const RECORD_HEADER_SIZE: u64 = 8;
+/// Log size below which the log is never compacted.
+const MIN_COMPACTION_SIZE: u64 = 1 << 20;
+/// Ratio of log size to live data size above which the log is compacted.
+const COMPACTION_RATIO: u64 = 2;
+
+/// File-backed key/value storage.
+///
+/// Entries are kept in memory and every insert is appended to a log file,
+/// so the contents survive restarts. The log is rewritten with only the
+/// live entries once it grows past `COMPACTION_RATIO` times their size.
+pub struct FileKeyValue(Mutex<FileKeyValueInner>);
+
+struct FileKeyValueInner {
+    path: PathBuf,
+    log: File,
+    entries: HashMap<Vec<u8>, Vec<u8>>,
+    log_size: u64,
+    live_size: u64,
+}
+
+impl FileKeyValue {
+    /// Opens (or creates) the log at `path` and replays its contents.
+    ///
+    /// A partially written record at the end of the log (e.g., after a crash)
+    /// is discarded.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let file_size = log.metadata()?.len();
+        let mut entries = HashMap::new();
+        let mut log_size = 0;
+        let mut reader = BufReader::new(&log);
+        while let Some((key, value)) = read_record(&mut reader, file_size - log_size)? {
+            log_size += record_size(&key, &value);
+            entries.insert(key, value);
+        }
+        drop(reader);
+        // Drop any trailing garbage so that new records are appended to a valid log.
+        log.set_len(log_size)?;
+
+        let live_size = entries.iter().map(|(k, v)| record_size(k, v)).sum();
+        let mut inner = FileKeyValueInner {
+            path,
+            log,
+            entries,
+            log_size,
+            live_size,
+        };
+        inner.maybe_compact()?;
+
+        Ok(FileKeyValue(Mutex::new(inner)))
+    }
+
+    /// Rewrites the log so that it only contains the live entries.
+    pub fn compact(&self) -> Result<()> {
+        self.0.lock().unwrap().compact()
+    }
+}
+
+impl FileKeyValueInner {
+    fn maybe_compact(&mut self) -> Result<()> {
+        if self.log_size > MIN_COMPACTION_SIZE && self.log_size > self.live_size * COMPACTION_RATIO
+        {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".compact");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            for (key, value) in &self.entries {
+                write_record(&mut writer, key, value)?;
+            }
+            writer
+                .into_inner()
+                .map_err(|err| anyhow!("failed to flush compacted log: {}", err))?
+                .sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.log = OpenOptions::new().append(true).open(&self.path)?;
+        self.log_size = self.live_size;
+        Ok(())
+    }
+}
+
+impl KeyValue for FileKeyValue {
+    fn get(&self, key: Vec<u8>) -> Result<Vec<u8>> {
+        self.0
+            .lock()
+            .unwrap()
+            .entries
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| anyhow!("not found"))
+    }
+
+    fn insert(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        let mut inner = self.0.lock().unwrap();
+
+        let mut record = Vec::with_capacity(record_size(&key, &value) as usize);
+        write_record(&mut record, &key, &value)?;
+        if let Err(err) = inner.log.write_all(&record) {
+            // Do not leave a partial record behind for later appends to follow.
+            let log_size = inner.log_size;
+            inner.log.set_len(log_size)?;
+            return Err(err.into());
+        }
+        inner.log_size += record.len() as u64;
+
+        inner.live_size += record.len() as u64;
+        if let Some(previous) = inner.entries.insert(key.clone(), value) {
+            inner.live_size -= record_size(&key, &previous);
+        }
+
+        inner.maybe_compact()
+    }
+}
+
+fn record_size(key: &[u8], value: &[u8]) -> u64 {
+    RECORD_HEADER_SIZE + key.len() as u64 + value.len() as u64
+}
+
+fn write_record<W: Write>(writer: &mut W, key: &[u8], value: &[u8]) -> Result<()> {
+    writer.write_all(&(key.len() as u32).to_be_bytes())?;
+    writer.write_all(&(value.len() as u32).to_be_bytes())?;
+    writer.write_all(key)?;
+    writer.write_all(value)?;
+    Ok(())
+}
+
+/// Reads the next record out of the `remaining` bytes of the log, returning
+/// `None` at the end of the log or if the remaining bytes do not form a
+/// complete record.
+fn read_record<R: Read>(reader: &mut R, remaining: u64) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut header = [0u8; RECORD_HEADER_SIZE as usize];
+    if !read_full(reader, &mut header)? {
+        return Ok(None);
+    }
+    let mut length = [0u8; 4];
+    length.copy_from_slice(&header[..4]);
+    let key_len = u32::from_be_bytes(length) as u64;
+    length.copy_from_slice(&header[4..]);
+    let value_len = u32::from_be_bytes(length) as u64;
+    // A torn header may claim more data than the log holds.
+    if RECORD_HEADER_SIZE + key_len + value_len > remaining {
+        return Ok(None);
+    }
+
+    let mut key = vec![0u8; key_len as usize];
+    let mut value = vec![0u8; value_len as usize];
+
+    if !read_full(reader, &mut key)? || !read_full(reader, &mut value)? {
+        return Ok(None);
+    }
+    Ok(Some((key, value)))
+}
+
+/// Fills `buf`, returning `false` if the reader ran out of data first.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => return Ok(false),
+            n => read += n,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("file-kv-{}-{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_file_kv_persists() {
+        let path = temp_path("persists");
+        {
+            let kv = FileKeyValue::open(&path).unwrap();
+            kv.insert(b"foo".to_vec(), b"bar".to_vec()).unwrap();
+            kv.insert(b"foo".to_vec(), b"baz".to_vec()).unwrap();
+            kv.insert(b"moo".to_vec(), vec![]).unwrap();
+        }
+
+        let kv = FileKeyValue::open(&path).unwrap();
+        assert_eq!(kv.get(b"foo".to_vec()).unwrap(), b"baz".to_vec());
+        assert_eq!(kv.get(b"moo".to_vec()).unwrap(), Vec::<u8>::new());
+        assert!(kv.get(b"boo".to_vec()).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_kv_compact() {
+        let path = temp_path("compact");
+        let kv = FileKeyValue::open(&path).unwrap();
+        for i in 0..100u8 {
+            kv.insert(b"key".to_vec(), vec![i; 64]).unwrap();
+        }
+        kv.compact().unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            record_size(b"key", &[0; 64])
+        );
+
+        // Appends after compaction must go to the new log.
+        kv.insert(b"other".to_vec(), b"value".to_vec()).unwrap();
+        drop(kv);
+
+        let kv = FileKeyValue::open(&path).unwrap();
+        assert_eq!(kv.get(b"key".to_vec()).unwrap(), vec![99; 64]);
+        assert_eq!(kv.get(b"other".to_vec()).unwrap(), b"value".to_vec());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_kv_truncated_record() {
+        let path = temp_path("truncated");
+        {
+            let kv = FileKeyValue::open(&path).unwrap();
+            kv.insert(b"foo".to_vec(), b"bar".to_vec()).unwrap();
+        }
+        // Simulate a crash in the middle of an append.
+        let mut log = OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(&[0, 0, 0, 3, 0, 0]).unwrap();
+        drop(log);
+
+        let kv = FileKeyValue::open(&path).unwrap();
+        assert_eq!(kv.get(b"foo".to_vec()).unwrap(), b"bar".to_vec());
+        kv.insert(b"moo".to_vec(), b"boo".to_vec()).unwrap();
+        drop(kv);
+
+        let kv = FileKeyValue::open(&path).unwrap();
+        assert_eq!(kv.get(b"moo".to_vec()).unwrap(), b"boo".to_vec());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_kv_oversized_record() {
+        let path = temp_path("oversized");
+        {
+            let kv = FileKeyValue::open(&path).unwrap();
+            kv.insert(b"foo".to_vec(), b"bar".to_vec()).unwrap();
+        }
+        // A torn header claiming far more data than the log holds.
+        let mut log = OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0])
+            .unwrap();
+        drop(log);
+
+        let kv = FileKeyValue::open(&path).unwrap();
+        assert_eq!(kv.get(b"foo".to_vec()).unwrap(), b"bar".to_vec());
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            record_size(b"foo", b"bar")
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! ewasm contract module validation.
+use parity_wasm::{
+    self,
+    elements::{External, ImportCountType, Instruction, Internal, Module, Type, ValueType},
+};
+use thiserror::Error;
+
+/// Magic bytes at the start of every wasm module.
+pub const WASM_MAGIC: &'static [u8] = b"\0asm";
+/// Namespace of the Ethereum Environment Interface imports.
+pub const EEI_NAMESPACE: &'static str = "ethereum";
+/// Name of the exported contract entry point.
+pub const MAIN_EXPORT: &'static str = "main";
+/// Name of the exported contract memory.
+pub const MEMORY_EXPORT: &'static str = "memory";
+
+/// ewasm module validation error.
+#[derive(Debug, Error)]
+pub enum WasmError {
+    #[error("malformed module: {0}")]
+    Malformed(String),
+    #[error("import {module}.{field} outside of the \"ethereum\" namespace")]
+    ForeignImport { module: String, field: String },
+    #[error("unexpected export: {0}")]
+    UnexpectedExport(String),
+    #[error("missing \"main\" function export")]
+    MissingMain,
+    #[error("\"main\" must take no arguments and return nothing")]
+    InvalidMainSignature,
+    #[error("missing \"memory\" export")]
+    MissingMemory,
+    #[error("floating-point types and instructions are not allowed")]
+    FloatingPoint,
+}
+
+/// Entity imported by a wasm module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Import {
+    /// Namespace of the import.
+    pub module: String,
+    /// Name of the import within its namespace.
+    pub field: String,
+    /// Kind of the import (`function`, `table`, `memory` or `global`).
+    pub kind: &'static str,
+}
+
+/// Entity exported by a wasm module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Export {
+    /// Name of the export.
+    pub field: String,
+    /// Kind of the export (`function`, `table`, `memory` or `global`).
+    pub kind: &'static str,
+}
+
+/// Returns whether `code` looks like a wasm module.
+pub fn is_wasm(code: &[u8]) -> bool {
+    code.starts_with(WASM_MAGIC)
+}
+
+/// Parses a wasm module without checking the ewasm contract interface.
+pub fn parse(code: &[u8]) -> Result<Module, WasmError> {
+    parity_wasm::deserialize_buffer(code).map_err(|err| WasmError::Malformed(err.to_string()))
+}
+
+/// Lists the imports and exports of a wasm module.
+pub fn interface(module: &Module) -> (Vec<Import>, Vec<Export>) {
+    let imports = module
+        .import_section()
+        .map(|s| s.entries())
+        .unwrap_or(&[])
+        .iter()
+        .map(|import| Import {
+            module: import.module().to_owned(),
+            field: import.field().to_owned(),
+            kind: match import.external() {
+                &External::Function(_) => "function",
+                &External::Table(_) => "table",
+                &External::Memory(_) => "memory",
+                &External::Global(_) => "global",
+            },
+        })
+        .collect();
+
+    let exports = module
+        .export_section()
+        .map(|s| s.entries())
+        .unwrap_or(&[])
+        .iter()
+        .map(|export| Export {
+            field: export.field().to_owned(),
+            kind: match export.internal() {
+                &Internal::Function(_) => "function",
+                &Internal::Table(_) => "table",
+                &Internal::Memory(_) => "memory",
+                &Internal::Global(_) => "global",
+            },
+        })
+        .collect();
+
+    (imports, exports)
+}
+
+/// Parses a wasm module and checks that it follows the ewasm contract
+/// interface: all imports come from the `ethereum` namespace, the only
+/// exports are a `main` function taking no arguments and returning nothing
+/// and the `memory`, and no floating-point types or instructions are used.
+pub fn validate(code: &[u8]) -> Result<Module, WasmError> {
+    let module = parse(code)?;
+
+    check_imports(&module)?;
+    check_exports(&module)?;
+    check_floats(&module)?;
+
+    Ok(module)
+}
+
+fn check_imports(module: &Module) -> Result<(), WasmError> {
+    let imports = module.import_section().map(|s| s.entries()).unwrap_or(&[]);
+    for import in imports {
+        if import.module() != EEI_NAMESPACE {
+            return Err(WasmError::ForeignImport {
+                module: import.module().to_owned(),
+                field: import.field().to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_exports(module: &Module) -> Result<(), WasmError> {
+    let exports = module.export_section().map(|s| s.entries()).unwrap_or(&[]);
+
+    let mut main = None;
+    let mut memory = false;
+    for export in exports {
+        match (export.field(), export.internal()) {
+            (MAIN_EXPORT, &Internal::Function(index)) => main = Some(index),
+            (MEMORY_EXPORT, &Internal::Memory(_)) => memory = true,
+            (field, _) => return Err(WasmError::UnexpectedExport(field.to_owned())),
+        }
+    }
+
+    let main = main.ok_or(WasmError::MissingMain)?;
+    if !memory {
+        return Err(WasmError::MissingMemory);
+    }
+
+    match function_type(module, main) {
+        Some(&Type::Function(ref ty)) if ty.params().is_empty() && ty.return_type().is_none() => {
+            Ok(())
+        }
+        _ => Err(WasmError::InvalidMainSignature),
+    }
+}
+
+/// Returns the type of the function at `index` in the function index space.
+fn function_type(module: &Module, index: u32) -> Option<&Type> {
+    let imported = module.import_count(ImportCountType::Function) as u32;
+    let type_ref = if index < imported {
+        module
+            .import_section()?
+            .entries()
+            .iter()
+            .filter_map(|import| match import.external() {
+                &External::Function(type_ref) => Some(type_ref),
+                _ => None,
+            })
+            .nth(index as usize)?
+    } else {
+        module
+            .function_section()?
+            .entries()
+            .get((index - imported) as usize)?
+            .type_ref()
+    };
+    module.type_section()?.types().get(type_ref as usize)
+}
+
+fn check_floats(module: &Module) -> Result<(), WasmError> {
+    let types = module.type_section().map(|s| s.types()).unwrap_or(&[]);
+    for &Type::Function(ref ty) in types {
+        if ty.params().iter().any(is_float) || ty.return_type().as_ref().map_or(false, is_float) {
+            return Err(WasmError::FloatingPoint);
+        }
+    }
+
+    let imports = module.import_section().map(|s| s.entries()).unwrap_or(&[]);
+    for import in imports {
+        if let &External::Global(ref global) = import.external() {
+            if is_float(&global.content_type()) {
+                return Err(WasmError::FloatingPoint);
+            }
+        }
+    }
+
+    let globals = module.global_section().map(|s| s.entries()).unwrap_or(&[]);
+    if globals
+        .iter()
+        .any(|global| is_float(&global.global_type().content_type()))
+    {
+        return Err(WasmError::FloatingPoint);
+    }
+
+    let bodies = module.code_section().map(|s| s.bodies()).unwrap_or(&[]);
+    for body in bodies {
+        if body
+            .locals()
+            .iter()
+            .any(|local| is_float(&local.value_type()))
+            || body.code().elements().iter().any(is_float_instruction)
+        {
+            return Err(WasmError::FloatingPoint);
+        }
+    }
+
+    Ok(())
+}
+
+fn is_float(ty: &ValueType) -> bool {
+    match *ty {
+        ValueType::F32 | ValueType::F64 => true,
+        _ => false,
+    }
+}
+
+fn is_float_instruction(instruction: &Instruction) -> bool {
+    use self::Instruction::*;
+
+    match *instruction {
+        F32Load(..) | F64Load(..) | F32Store(..) | F64Store(..) | F32Const(_) | F64Const(_)
+        | F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge | F64Eq | F64Ne | F64Lt | F64Gt | F64Le
+        | F64Ge | F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt
+        | F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign | F64Abs | F64Neg
+        | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt | F64Add | F64Sub | F64Mul
+        | F64Div | F64Min | F64Max | F64Copysign | I32TruncSF32 | I32TruncUF32 | I32TruncSF64
+        | I32TruncUF64 | I64TruncSF32 | I64TruncUF32 | I64TruncSF64 | I64TruncUF64
+        | F32ConvertSI32 | F32ConvertUI32 | F32ConvertSI64 | F32ConvertUI64 | F32DemoteF64
+        | F64ConvertSI32 | F64ConvertUI32 | F64ConvertSI64 | F64ConvertUI64 | F64PromoteF32
+        | I32ReinterpretF32 | I64ReinterpretF64 | F32ReinterpretI32 | F64ReinterpretI64 => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Module header followed by a `() -> ()` type.
+    const HEADER: &'static [u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic, version
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // types: () -> ()
+    ];
+    /// Function, memory and export sections of a module with one function,
+    /// exported as `main`, and an exported memory.
+    const BODY: &'static [u8] = &[
+        0x03, 0x02, 0x01, 0x00, // functions: type 0
+        0x05, 0x03, 0x01, 0x00, 0x01, // memory: min 1 page
+        0x07, 0x11, 0x02, // exports
+        0x04, 0x6d, 0x61, 0x69, 0x6e, 0x00, 0x00, // "main": function 0
+        0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, // "memory": memory 0
+    ];
+    /// Import section with `env.foo: () -> ()`.
+    const ENV_IMPORT: &'static [u8] = &[
+        0x02, 0x0b, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x03, 0x66, 0x6f, 0x6f, 0x00, 0x00,
+    ];
+    /// Code section with an empty function.
+    const EMPTY_CODE: &'static [u8] = &[0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b];
+    /// Code section with a function executing `f32.const 0; drop`.
+    const FLOAT_CODE: &'static [u8] = &[
+        0x0a, 0x0a, 0x01, 0x08, 0x00, 0x43, 0x00, 0x00, 0x00, 0x00, 0x1a, 0x0b,
+    ];
+
+    fn module(sections: &[&[u8]]) -> Vec<u8> {
+        sections.concat()
+    }
+
+    #[test]
+    fn test_valid_module() {
+        let code = module(&[HEADER, BODY, EMPTY_CODE]);
+        assert!(is_wasm(&code));
+        assert!(validate(&code).is_ok());
+    }
+
+    #[test]
+    fn test_erc20_module() {
+        let code = include_bytes!("../../resources/erc20/erc20.wasm");
+        assert!(is_wasm(code));
+        assert!(validate(code).is_ok());
+
+        let (imports, exports) = interface(&parse(code).unwrap());
+        assert!(imports.iter().all(|import| import.module == EEI_NAMESPACE));
+        assert_eq!(
+            exports,
+            vec![
+                Export {
+                    field: MEMORY_EXPORT.to_owned(),
+                    kind: "memory",
+                },
+                Export {
+                    field: MAIN_EXPORT.to_owned(),
+                    kind: "function",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_module() {
+        let code = module(&[HEADER, &[0x03, 0xff]]);
+        assert!(matches!(validate(&code), Err(WasmError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_foreign_import() {
+        // The imported function shifts `main` to index 1, which is fine as
+        // validation stops at the import.
+        let code = module(&[HEADER, ENV_IMPORT, BODY, EMPTY_CODE]);
+        assert!(matches!(
+            validate(&code),
+            Err(WasmError::ForeignImport { .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_exports() {
+        let code = module(&[HEADER, &BODY[..9], EMPTY_CODE]);
+        assert!(matches!(validate(&code), Err(WasmError::MissingMain)));
+    }
+
+    #[test]
+    fn test_float_instruction() {
+        let code = module(&[HEADER, BODY, FLOAT_CODE]);
+        assert!(matches!(validate(&code), Err(WasmError::FloatingPoint)));
+    }
+}
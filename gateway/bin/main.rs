@@ -40,8 +40,10 @@ use fdlimit::raise_fd_limit;
 use slog::{error, info};
 
 use oasis_core_runtime::common::logger::{get_logger, init_logger};
-use oasis_ethwasi_runtime_common::MIN_GAS_PRICE_GWEI;
-use web3_gateway::util;
+use oasis_ethwasi_runtime_common::{
+    SizeLimits, MAX_BATCH_SIZE, MAX_INIT_CODE_SIZE, MAX_TX_SIZE, MIN_GAS_PRICE_GWEI,
+};
+use web3_gateway::{util, CacheConfig, RetryPolicy, RetryReason};
 
 const METRICS_MODE_PULL: &str = "pull";
 const METRICS_MODE_PUSH: &str = "push";
@@ -52,6 +54,22 @@ fn main() -> Result<()> {
     raise_fd_limit();
 
     let gas_price = MIN_GAS_PRICE_GWEI.to_string();
+    let max_tx_size = MAX_TX_SIZE.to_string();
+    let max_init_code_size = MAX_INIT_CODE_SIZE.to_string();
+    let max_batch_size = MAX_BATCH_SIZE.to_string();
+    let default_retry_policy = RetryPolicy::default();
+    let tx_retry_on = default_retry_policy
+        .reasons
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let tx_max_retries = default_retry_policy.max_retries.to_string();
+    let tx_retry_backoff = (default_retry_policy.initial_backoff.as_millis() as u64).to_string();
+    let tx_retry_max_backoff = (default_retry_policy.max_backoff.as_millis() as u64).to_string();
+    let default_cache_config = CacheConfig::default();
+    let block_cache_size = default_cache_config.blocks.to_string();
+    let transaction_cache_size = default_cache_config.transactions.to_string();
 
     let args = App::new("Oasis Eth/WASI Runtime Web3 Gateway")
         .arg(
@@ -106,7 +124,7 @@ fn main() -> Result<()> {
         .arg(
             Arg::with_name("pubsub-interval")
                 .long("pubsub-interval")
-                .help("Time interval used for pub/sub notifications (in sec).")
+                .help("Time interval for polling new blocks while the block watch is unavailable (in sec).")
                 .default_value("3")
                 .takes_value(true),
         )
@@ -117,6 +135,76 @@ fn main() -> Result<()> {
                 .default_value(&gas_price)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("max-tx-size")
+                .long("max-tx-size")
+                .help("Max size of a raw transaction (in bytes).")
+                .default_value(&max_tx_size)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-init-code-size")
+                .long("max-init-code-size")
+                .help("Max size of contract init code (in bytes).")
+                .default_value(&max_init_code_size)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-batch-size")
+                .long("max-batch-size")
+                .help("Max total size of the transactions in a batch (in bytes).")
+                .default_value(&max_batch_size)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("async-submission")
+                .long("async-submission")
+                .help("Return transaction hashes from eth_sendRawTransaction without waiting for the transactions to be executed."),
+        )
+        .arg(
+            Arg::with_name("tx-retry-on")
+                .long("tx-retry-on")
+                .help("Errors on which transaction submission is retried.")
+                .possible_values(RetryReason::NAMES)
+                .use_delimiter(true)
+                .default_value(&tx_retry_on)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tx-max-retries")
+                .long("tx-max-retries")
+                .help("Max number of times a transaction submission is retried.")
+                .default_value(&tx_max_retries)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tx-retry-backoff")
+                .long("tx-retry-backoff")
+                .help("Delay before the first transaction submission retry (in ms), doubled on each retry.")
+                .default_value(&tx_retry_backoff)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tx-retry-max-backoff")
+                .long("tx-retry-max-backoff")
+                .help("Max delay between transaction submission retries (in ms).")
+                .default_value(&tx_retry_max_backoff)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("block-cache-size")
+                .long("block-cache-size")
+                .help("Number of blocks fetched from the node to keep in memory.")
+                .default_value(&block_cache_size)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("transaction-cache-size")
+                .long("transaction-cache-size")
+                .help("Number of transactions fetched from the node to keep in memory.")
+                .default_value(&transaction_cache_size)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("jsonrpc-max-batch")
                 .long("jsonrpc-max-batch")
@@ -194,6 +282,22 @@ fn main() -> Result<()> {
     let ws_rate_limit = value_t!(args, "ws-rate-limit", usize)?;
     let pubsub_interval_secs = value_t!(args, "pubsub-interval", u64)?;
     let gas_price = util::gwei_to_wei(value_t!(args, "gas-price", u64)?);
+    let size_limits = SizeLimits {
+        max_tx_size: value_t!(args, "max-tx-size", usize)?,
+        max_init_code_size: value_t!(args, "max-init-code-size", usize)?,
+        max_batch_size: value_t!(args, "max-batch-size", usize)?,
+    };
+    let async_submission = args.is_present("async-submission");
+    let retry_policy = RetryPolicy {
+        reasons: values_t!(args, "tx-retry-on", RetryReason)?,
+        max_retries: value_t!(args, "tx-max-retries", usize)?,
+        initial_backoff: Duration::from_millis(value_t!(args, "tx-retry-backoff", u64)?),
+        max_backoff: Duration::from_millis(value_t!(args, "tx-retry-max-backoff", u64)?),
+    };
+    let cache_config = CacheConfig {
+        blocks: value_t!(args, "block-cache-size", usize)?,
+        transactions: value_t!(args, "transaction-cache-size", usize)?,
+    };
     let jsonrpc_max_batch_size = value_t!(args, "jsonrpc-max-batch", usize)?;
 
     // Metrics.
@@ -233,6 +337,10 @@ fn main() -> Result<()> {
         ws_max_connections,
         ws_rate_limit,
         gas_price,
+        size_limits,
+        async_submission,
+        retry_policy,
+        cache_config,
         jsonrpc_max_batch_size,
     );
 
@@ -0,0 +1,194 @@
+//! Caches of chain data fetched from the node.
+use std::{hash::Hash, sync::Arc};
+
+use ethcore::transaction::UnverifiedTransaction;
+use ethereum_types::H256;
+use lazy_static::lazy_static;
+use lru_cache::LruCache;
+use oasis_core_client::transaction::snapshot::BlockSnapshot;
+use parking_lot::Mutex;
+use prometheus::{labels, register_int_counter_vec, IntCounterVec};
+
+use crate::translator::EthereumTransaction;
+
+// Metrics.
+lazy_static! {
+    static ref CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "web3_gateway_cache_lookups",
+        "Number of chain cache lookups by result",
+        &["cache", "result"]
+    )
+    .unwrap();
+}
+
+/// Number of entries kept by the chain caches.
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    /// Number of blocks, together with their decoded transactions.
+    pub blocks: usize,
+    /// Number of transactions looked up by hash or index, together with
+    /// their receipts.
+    pub transactions: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            blocks: 1024,
+            transactions: 16384,
+        }
+    }
+}
+
+/// Bounded LRU cache which counts its hits and misses.
+struct Lru<K: Eq + Hash, V: Clone> {
+    name: &'static str,
+    entries: Mutex<LruCache<K, V>>,
+}
+
+impl<K: Eq + Hash, V: Clone> Lru<K, V> {
+    fn new(name: &'static str, capacity: usize) -> Self {
+        Lru {
+            name,
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        let value = self.entries.lock().get_mut(key).cloned();
+        let result = if value.is_some() { "hit" } else { "miss" };
+        CACHE_LOOKUPS
+            .with(&labels! {"cache" => self.name, "result" => result,})
+            .inc();
+        value
+    }
+
+    fn insert(&self, key: K, value: V) {
+        self.entries.lock().insert(key, value);
+    }
+}
+
+/// Caches of blocks and transactions fetched from the node.
+///
+/// Blocks and the transactions in them never change once they are final, so
+/// entries are never invalidated, only evicted. Lookups of the latest block
+/// always go to the node, but the block it returns is cached like any other.
+///
+/// Transactions are stored by their position in the chain. The same Ethereum
+/// transaction can appear more than once, e.g., after attempts which the
+/// runtime rejected, so only executed transactions are looked up by hash.
+pub struct ChainCache {
+    blocks: Lru<u64, BlockSnapshot>,
+    block_rounds: Lru<H256, u64>,
+    block_transactions: Lru<u64, Arc<Vec<UnverifiedTransaction>>>,
+    transactions: Lru<(u64, u32), Arc<EthereumTransaction>>,
+    transaction_positions: Lru<H256, (u64, u32)>,
+}
+
+impl ChainCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        ChainCache {
+            blocks: Lru::new("blocks", config.blocks),
+            block_rounds: Lru::new("block_rounds", config.blocks),
+            block_transactions: Lru::new("block_transactions", config.blocks),
+            transactions: Lru::new("transactions", config.transactions),
+            transaction_positions: Lru::new("transaction_positions", config.transactions),
+        }
+    }
+
+    /// Returns the block with the given round.
+    pub fn block(&self, round: u64) -> Option<BlockSnapshot> {
+        self.blocks.get(&round)
+    }
+
+    /// Returns the block with the given hash.
+    pub fn block_by_hash(&self, hash: &H256) -> Option<BlockSnapshot> {
+        self.block_rounds
+            .get(hash)
+            .and_then(|round| self.block(round))
+    }
+
+    pub fn insert_block(&self, snapshot: &BlockSnapshot) {
+        let round = snapshot.block.header.round;
+        self.blocks.insert(round, snapshot.clone());
+        self.block_rounds
+            .insert(snapshot.block_hash.as_ref().into(), round);
+    }
+
+    /// Returns the decoded Ethereum transactions of the block with the given
+    /// round.
+    pub fn block_transactions(&self, round: u64) -> Option<Arc<Vec<UnverifiedTransaction>>> {
+        self.block_transactions.get(&round)
+    }
+
+    pub fn insert_block_transactions(&self, round: u64, txns: Arc<Vec<UnverifiedTransaction>>) {
+        self.block_transactions.insert(round, txns);
+    }
+
+    /// Returns the executed transaction with the given hash.
+    pub fn transaction(&self, hash: &H256) -> Option<Arc<EthereumTransaction>> {
+        self.transaction_positions
+            .get(hash)
+            .and_then(|(round, index)| self.transaction_at(round, index))
+    }
+
+    /// Returns the transaction at the given index of the block with the
+    /// given round.
+    pub fn transaction_at(&self, round: u64, index: u32) -> Option<Arc<EthereumTransaction>> {
+        self.transactions.get(&(round, index))
+    }
+
+    pub fn insert_transaction(&self, hash: H256, txn: Arc<EthereumTransaction>) {
+        let position = (txn.round(), txn.index());
+        self.insert_position(hash, position, txn.is_executed());
+        self.transactions.insert(position, txn);
+    }
+
+    /// Records the position of a transaction with the given hash, unless it
+    /// was rejected by the runtime.
+    fn insert_position(&self, hash: H256, position: (u64, u32), executed: bool) {
+        if executed {
+            self.transaction_positions.insert(hash, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru() {
+        let cache = Lru::new("test", 2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.get(&1), Some("one"));
+
+        // The least recently used entry is evicted first.
+        cache.insert(3, "three");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("one"));
+        assert_eq!(cache.get(&3), Some("three"));
+
+        // A cache without capacity keeps nothing.
+        let cache = Lru::new("test", 0);
+        cache.insert(1, "one");
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_rejected_attempt() {
+        let cache = ChainCache::new(&CacheConfig::default());
+        let hash = H256::from(1);
+
+        // Rejected attempts before and after the executed transaction do not
+        // change where the transaction is looked up by hash.
+        cache.insert_position(hash, (5, 0), false);
+        cache.insert_position(hash, (6, 2), true);
+        cache.insert_position(hash, (7, 1), false);
+        assert_eq!(cache.transaction_positions.get(&hash), Some((6, 2)));
+
+        cache.insert_position(H256::from(2), (8, 0), false);
+        assert_eq!(cache.transaction_positions.get(&H256::from(2)), None);
+    }
+}
@@ -16,14 +16,17 @@
 
 //! Eth rpc implementation.
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
 use anyhow::Error;
 use ethcore::{filter::Filter as EthcoreFilter, ids::BlockId};
 use ethereum_types::{Address, H256, H64, U256};
 use jsonrpc_core::{
     futures::{future, Future},
-    BoxFuture, Result,
+    BoxFuture, Params, Result, Value,
 };
 use jsonrpc_macros::Trailing;
 use lazy_static::lazy_static;
@@ -44,8 +47,14 @@ use prometheus::{
 use slog::{debug, info, Logger};
 
 use crate::{
-    translator::Translator,
-    util::{block_number_to_id, execution_error, jsonrpc_error},
+    traits::eth::{
+        EthAccessList, EthFees, EthProof, RpcAccessListItem, RpcAccessListResult, RpcAccountProof,
+        RpcFeeHistory, RpcKeyProof, RpcStateOverride, RpcStorageProof,
+    },
+    translator::{
+        AccessList, AccountOverride, EstimateGasError, KeyProof, StateOverride, Translator,
+    },
+    util::{block_number_to_id, execution_error, jsonrpc_error, vm_error},
 };
 
 // Metrics.
@@ -118,6 +127,140 @@ impl EthClient {
             translator,
         }
     }
+
+    /// Handles `eth_call` with Geth-style state overrides, which are passed
+    /// after the block number and cannot be expressed by the `Eth` trait.
+    pub fn call_with_params(&self, meta: Metadata, params: Params) -> BoxFuture<Value> {
+        let (request, num, overrides) = try_bf!(parse_call_params(params));
+        let overrides = try_bf!(state_override(overrides.unwrap_or_default()));
+
+        Box::new(
+            self.call_with_overrides(meta, request, num.unwrap_or_default(), overrides)
+                .map(|output| {
+                    serde_json::to_value(output).expect("bytes are always serializable; qed")
+                }),
+        )
+    }
+
+    fn call_with_overrides(
+        &self,
+        meta: Metadata,
+        request: CallRequest,
+        num: BlockNumber,
+        overrides: StateOverride,
+    ) -> BoxFuture<Bytes> {
+        ETH_RPC_CALLS.with(&labels! {"call" => "call",}).inc();
+        let timer = ETH_RPC_CALL_TIME
+            .with(&labels! {"call" => "call",})
+            .start_timer();
+
+        info!(
+            self.logger,
+            "eth_call";
+                "request" => ?request,
+                "num" => ?num,
+                "overrides" => ?overrides
+        );
+
+        let signed = try_bf!(fake_sign::sign_call(request.into(), meta.is_dapp()));
+
+        Box::new(
+            self.translator
+                .simulate_transaction(signed, block_number_to_id(num), overrides)
+                .map_err(errors::call)
+                .and_then(|executed| match executed.exception {
+                    Some(ref exception) => Err(errors::vm(exception, &executed.output)),
+                    None => Ok(executed),
+                })
+                .map(|executed| executed.output.into())
+                .then(move |result| {
+                    drop(timer);
+                    result
+                }),
+        )
+    }
+}
+
+/// Parses the parameters of `eth_call`: a call request, optionally followed
+/// by a block number and a set of state overrides.
+fn parse_call_params(
+    params: Params,
+) -> Result<(CallRequest, Option<BlockNumber>, Option<RpcStateOverride>)> {
+    let mut values: Vec<Value> = params.parse()?;
+    if values.is_empty() || values.len() > 3 {
+        return Err(errors::invalid_params(
+            "eth_call",
+            "Expected a call request, a block number and state overrides.",
+        ));
+    }
+    values.resize(3, Value::Null);
+
+    serde_json::from_value(Value::Array(values))
+        .map_err(|err| errors::invalid_params("eth_call", err))
+}
+
+/// Converts state overrides into the form used for simulation.
+fn state_override(overrides: RpcStateOverride) -> Result<StateOverride> {
+    overrides
+        .into_iter()
+        .map(|(address, account)| {
+            if account.state.is_some() && account.state_diff.is_some() {
+                return Err(errors::invalid_params(
+                    "eth_call",
+                    format!("Both state and stateDiff given for account {:?}.", address),
+                ));
+            }
+
+            let slots = |slots: HashMap<RpcH256, RpcH256>| {
+                slots
+                    .into_iter()
+                    .map(|(key, value)| (key.into(), value.into()))
+                    .collect()
+            };
+
+            Ok((
+                address.into(),
+                AccountOverride {
+                    balance: account.balance.map(Into::into),
+                    nonce: account.nonce.map(Into::into),
+                    code: account.code.map(Into::into),
+                    storage: account.state.map(slots),
+                    storage_diff: account.state_diff.map(slots).unwrap_or_default(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Converts an MKVS inclusion proof into its RPC representation.
+fn rpc_key_proof(proof: KeyProof) -> RpcKeyProof {
+    RpcKeyProof {
+        key: proof.key.into(),
+        value: proof.value.map(Into::into),
+        untrusted_root: H256::from(proof.proof.untrusted_root.as_ref()).into(),
+        entries: proof
+            .proof
+            .entries
+            .into_iter()
+            .map(|entry| entry.map(Into::into))
+            .collect(),
+    }
+}
+
+/// Checks that reward percentiles are ascending numbers between 0 and 100.
+fn check_percentiles(percentiles: &[f64]) -> Result<()> {
+    let mut previous = 0.0;
+    for &percentile in percentiles {
+        // Comparisons with NaN are false, so it must be rejected explicitly.
+        if percentile.is_nan() || percentile < previous || percentile > 100.0 {
+            return Err(errors::invalid_params(
+                "rewardPercentiles",
+                "Expected ascending percentiles between 0 and 100.",
+            ));
+        }
+        previous = percentile;
+    }
+    Ok(())
 }
 
 impl Eth for EthClient {
@@ -246,10 +389,22 @@ impl Eth for EthClient {
                 "num" => ?num
         );
 
+        // Account for transactions submitted through this gateway which are
+        // not yet mined.
+        let pending_nonce = match num {
+            BlockNumber::Pending => self.translator.pending_pool().next_nonce(&address),
+            _ => None,
+        };
+
         Box::new(
             self.translator
                 .get_block_unwrap(block_number_to_id(num))
-                .and_then(move |blk| Ok(blk.state()?.nonce(&address)?.into()))
+                .and_then(move |blk| {
+                    let nonce = blk.state()?.nonce(&address)?;
+                    Ok(pending_nonce
+                        .map_or(nonce, |pending| pending.max(nonce))
+                        .into())
+                })
                 .map_err(jsonrpc_error),
         )
     }
@@ -399,13 +554,15 @@ impl Eth for EthClient {
         info!(self.logger, "eth_getTransactionByHash"; "hash" => ?hash);
 
         let hash = hash.into();
+        let pending = self.translator.pending_pool().clone();
 
         Box::new(
             self.translator
                 .get_txn_by_hash(hash)
-                .and_then(move |txn| {
-                    txn.map(|txn| Ok(RpcTransaction::from_localized(txn.transaction()?)))
-                        .transpose()
+                .and_then(move |txn| match txn {
+                    Some(txn) => Ok(Some(RpcTransaction::from_localized(txn.transaction()?))),
+                    // Fall back to transactions which are not yet mined.
+                    None => Ok(pending.get(&hash).map(RpcTransaction::from_signed)),
                 })
                 .map_err(jsonrpc_error),
         )
@@ -567,6 +724,19 @@ impl Eth for EthClient {
             info!(self.logger, "eth_sendRawTransaction")
         }
 
+        if self.translator.async_submission() {
+            return Box::new(
+                self.translator
+                    .submit_raw_transaction(raw.into())
+                    .map(Into::into)
+                    .map_err(execution_error)
+                    .then(move |result| {
+                        drop(timer);
+                        result
+                    }),
+            );
+        }
+
         Box::new(
             self.translator
                 .send_raw_transaction(raw.into())
@@ -594,31 +764,7 @@ impl Eth for EthClient {
         request: CallRequest,
         num: Trailing<BlockNumber>,
     ) -> BoxFuture<Bytes> {
-        ETH_RPC_CALLS.with(&labels! {"call" => "call",}).inc();
-        let timer = ETH_RPC_CALL_TIME
-            .with(&labels! {"call" => "call",})
-            .start_timer();
-
-        let num = num.unwrap_or_default();
-
-        info!(self.logger, "eth_call"; "request" => ?request, "num" => ?num);
-
-        let signed = try_bf!(fake_sign::sign_call(request.into(), meta.is_dapp()));
-
-        Box::new(
-            self.translator
-                .simulate_transaction(signed, block_number_to_id(num))
-                .map_err(errors::call)
-                .and_then(|executed| match executed.exception {
-                    Some(ref exception) => Err(errors::vm(exception, &executed.output)),
-                    None => Ok(executed),
-                })
-                .map(|executed| executed.output.into())
-                .then(move |result| {
-                    drop(timer);
-                    result
-                }),
-        )
+        self.call_with_overrides(meta, request, num.unwrap_or_default(), StateOverride::new())
     }
 
     fn estimate_gas(
@@ -638,12 +784,18 @@ impl Eth for EthClient {
 
         info!(self.logger, "eth_estimateGas"; "request" => ?request, "num" => ?num);
 
+        let gas_cap = request.gas.map(Into::into);
         let signed = try_bf!(fake_sign::sign_call(request.into(), meta.is_dapp()));
 
         Box::new(
             self.translator
-                .estimate_gas(signed, block_number_to_id(num))
-                .map_err(execution_error)
+                .estimate_gas(signed, gas_cap, block_number_to_id(num))
+                .map_err(|err| match err {
+                    EstimateGasError::Call(err) => execution_error(err),
+                    EstimateGasError::Execution { exception, output } => {
+                        vm_error(&exception, &output)
+                    }
+                })
                 .map(Into::into)
                 .then(move |result| {
                     drop(timer);
@@ -677,3 +829,329 @@ impl Eth for EthClient {
         ))
     }
 }
+
+impl EthProof for EthClient {
+    type Metadata = Metadata;
+
+    fn proof(
+        &self,
+        address: RpcH160,
+        keys: Vec<RpcH256>,
+        num: Trailing<BlockNumber>,
+    ) -> BoxFuture<RpcAccountProof> {
+        ETH_RPC_CALLS.with(&labels! {"call" => "getProof",}).inc();
+        let timer = ETH_RPC_CALL_TIME
+            .with(&labels! {"call" => "getProof",})
+            .start_timer();
+
+        let address: Address = RpcH160::into(address);
+        let keys: Vec<H256> = keys.into_iter().map(RpcH256::into).collect();
+        let num = num.unwrap_or_default();
+
+        info!(
+            self.logger,
+            "eth_getProof";
+                "address" => ?address,
+                "keys" => ?keys,
+                "num" => ?num
+        );
+
+        Box::new(
+            self.translator
+                .get_proof(address, keys, block_number_to_id(num))
+                .map(move |proof| RpcAccountProof {
+                    address: address.into(),
+                    state_root: proof.state_root.into(),
+                    balance: proof.balance.into(),
+                    nonce: proof.nonce.into(),
+                    account_proof: rpc_key_proof(proof.account),
+                    storage_proof: proof
+                        .storage
+                        .into_iter()
+                        .map(|storage| RpcStorageProof {
+                            key: storage.slot.into(),
+                            value: storage.value.into(),
+                            proof: storage.proof.map(rpc_key_proof),
+                        })
+                        .collect(),
+                })
+                .map_err(jsonrpc_error)
+                .then(move |result| {
+                    drop(timer);
+                    result
+                }),
+        )
+    }
+}
+
+impl EthAccessList for EthClient {
+    type Metadata = Metadata;
+
+    fn create_access_list(
+        &self,
+        meta: Self::Metadata,
+        request: CallRequest,
+        num: Trailing<BlockNumber>,
+    ) -> BoxFuture<RpcAccessListResult> {
+        ETH_RPC_CALLS
+            .with(&labels! {"call" => "createAccessList",})
+            .inc();
+        let timer = ETH_RPC_CALL_TIME
+            .with(&labels! {"call" => "createAccessList",})
+            .start_timer();
+
+        let num = num.unwrap_or_default();
+
+        info!(self.logger, "eth_createAccessList"; "request" => ?request, "num" => ?num);
+
+        let signed = try_bf!(fake_sign::sign_call(request.into(), meta.is_dapp()));
+
+        Box::new(
+            self.translator
+                .create_access_list(signed, block_number_to_id(num))
+                .map_err(errors::call)
+                .map(|access_list| {
+                    let AccessList {
+                        accounts,
+                        gas_used,
+                        exception,
+                        output,
+                    } = access_list;
+
+                    RpcAccessListResult {
+                        access_list: accounts
+                            .into_iter()
+                            .map(|(address, slots)| RpcAccessListItem {
+                                address: address.into(),
+                                storage_keys: slots.into_iter().map(Into::into).collect(),
+                            })
+                            .collect(),
+                        gas_used: gas_used.into(),
+                        error: exception.map(|exception| vm_error(&exception, &output).message),
+                    }
+                })
+                .then(move |result| {
+                    drop(timer);
+                    result
+                }),
+        )
+    }
+}
+
+impl EthFees for EthClient {
+    type Metadata = Metadata;
+
+    fn fee_history(
+        &self,
+        block_count: RpcU256,
+        newest: BlockNumber,
+        percentiles: Trailing<Vec<f64>>,
+    ) -> BoxFuture<RpcFeeHistory> {
+        ETH_RPC_CALLS.with(&labels! {"call" => "feeHistory",}).inc();
+        let timer = ETH_RPC_CALL_TIME
+            .with(&labels! {"call" => "feeHistory",})
+            .start_timer();
+
+        let block_count = U256::from(block_count)
+            .min(u64::max_value().into())
+            .low_u64();
+        let percentiles: Option<Vec<f64>> = percentiles.into();
+
+        info!(
+            self.logger,
+            "eth_feeHistory";
+                "block_count" => block_count,
+                "newest" => ?newest,
+                "percentiles" => ?percentiles
+        );
+
+        if let Some(ref percentiles) = percentiles {
+            if let Err(err) = check_percentiles(percentiles) {
+                return Box::new(future::err(err));
+            }
+        }
+        let with_reward = percentiles.is_some();
+
+        Box::new(
+            self.translator
+                .fee_history(
+                    block_count,
+                    block_number_to_id(newest),
+                    percentiles.unwrap_or_default(),
+                )
+                .map(move |history| RpcFeeHistory {
+                    oldest_block: history.oldest_block.into(),
+                    base_fee_per_gas: history
+                        .base_fee_per_gas
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    gas_used_ratio: history.gas_used_ratio,
+                    reward: if with_reward {
+                        Some(
+                            history
+                                .reward
+                                .into_iter()
+                                .map(|rewards| rewards.into_iter().map(Into::into).collect())
+                                .collect(),
+                        )
+                    } else {
+                        None
+                    },
+                })
+                .map_err(jsonrpc_error)
+                .then(move |result| {
+                    drop(timer);
+                    result
+                }),
+        )
+    }
+
+    fn max_priority_fee_per_gas(&self) -> Result<RpcU256> {
+        ETH_RPC_CALLS
+            .with(&labels! {"call" => "maxPriorityFeePerGas",})
+            .inc();
+        Ok(self.translator.max_priority_fee().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+    use io_context::Context;
+    use oasis_core_runtime::{
+        common::crypto::hash::Hash,
+        storage::{
+            mkvs::{
+                sync::{GetRequest, NoopReadSyncer, Proof, ReadSync, TreeID},
+                OverlayTree, Root, RootType, Tree,
+            },
+            MKVS,
+        },
+    };
+    use oasis_ethwasi_runtime_common::proof;
+
+    use crate::traits::eth::RpcAccountOverride;
+
+    fn params(json: &str) -> Params {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse_call_params() {
+        let to = r#"{"to":"0x0000000000000000000000000000000000000001"}"#;
+
+        let (_, num, overrides) = parse_call_params(params(&format!("[{}]", to))).unwrap();
+        assert!(num.is_none());
+        assert!(overrides.is_none());
+
+        let (_, num, overrides) =
+            parse_call_params(params(&format!(r#"[{}, "latest"]"#, to))).unwrap();
+        assert_eq!(num, Some(BlockNumber::Latest));
+        assert!(overrides.is_none());
+
+        let (_, _, overrides) = parse_call_params(params(&format!(
+            r#"[{}, "latest", {{
+                "0x0000000000000000000000000000000000000002": {{
+                    "balance": "0x10",
+                    "code": "0x3331600055",
+                    "stateDiff": {{
+                        "0x0000000000000000000000000000000000000000000000000000000000000001":
+                        "0x0000000000000000000000000000000000000000000000000000000000000002"
+                    }}
+                }}
+            }}]"#,
+            to
+        )))
+        .unwrap();
+        let overrides = state_override(overrides.unwrap()).unwrap();
+        let account = &overrides[&Address::from(2)];
+        assert_eq!(account.balance, Some(U256::from(16)));
+        assert_eq!(account.code, Some(hex::decode("3331600055").unwrap()));
+        assert!(account.storage.is_none());
+        assert_eq!(account.storage_diff[&H256::from(1)], H256::from(2));
+
+        assert!(parse_call_params(params("[]")).is_err());
+        assert!(parse_call_params(params(&format!(r#"[{}, "latest", {{}}, 1]"#, to))).is_err());
+    }
+
+    #[test]
+    fn test_state_override_conflict() {
+        let mut overrides = RpcStateOverride::new();
+        overrides.insert(
+            Address::from(2).into(),
+            RpcAccountOverride {
+                state: Some(HashMap::new()),
+                state_diff: Some(HashMap::new()),
+                ..Default::default()
+            },
+        );
+        assert!(state_override(overrides).is_err());
+    }
+
+    #[test]
+    fn test_check_percentiles() {
+        assert!(check_percentiles(&[]).is_ok());
+        assert!(check_percentiles(&[0.0, 25.0, 25.0, 100.0]).is_ok());
+        assert!(check_percentiles(&[50.0, 25.0]).is_err());
+        assert!(check_percentiles(&[-1.0]).is_err());
+        assert!(check_percentiles(&[100.5]).is_err());
+        assert!(check_percentiles(&[10.0, ::std::f64::NAN]).is_err());
+        assert!(check_percentiles(&[::std::f64::INFINITY]).is_err());
+    }
+
+    #[test]
+    fn test_key_proof_round_trip() {
+        let key = Address::from(1).to_vec();
+        let value = b"account".to_vec();
+
+        let mut tree = Tree::make()
+            .with_root_type(RootType::State)
+            .new(Box::new(NoopReadSyncer {}));
+        let mut overlay = OverlayTree::new(&mut tree);
+        MKVS::insert(&mut overlay, Context::background(), &key, &value);
+        MKVS::insert(&mut overlay, Context::background(), b"other", b"value");
+        let (_, state_root) = overlay
+            .commit_both(Context::background(), Default::default(), 0)
+            .unwrap();
+        let request = GetRequest {
+            tree: TreeID {
+                root: Root {
+                    root_type: RootType::State,
+                    hash: state_root,
+                    ..Default::default()
+                },
+                position: state_root,
+            },
+            key: key.clone(),
+            include_siblings: false,
+        };
+        let key_proof = KeyProof {
+            key: key.clone(),
+            value: Some(value.clone()),
+            proof: tree.sync_get(Context::background(), request).unwrap().proof,
+        };
+
+        // Encode the proof as returned by eth_getProof and decode it the way
+        // a client would.
+        let json = serde_json::to_string(&rpc_key_proof(key_proof)).unwrap();
+        let rpc: RpcKeyProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(rpc.key.0, key);
+        assert_eq!(rpc.value.map(|value| value.0), Some(value.clone()));
+
+        let untrusted_root: H256 = rpc.untrusted_root.into();
+        let proof = Proof {
+            untrusted_root: Hash::from(untrusted_root.as_ref() as &[u8]),
+            entries: rpc
+                .entries
+                .into_iter()
+                .map(|entry| entry.map(|entry| entry.0))
+                .collect(),
+        };
+        assert_eq!(
+            proof::verify(state_root, &rpc.key.0, proof).unwrap(),
+            Some(value)
+        );
+    }
+}
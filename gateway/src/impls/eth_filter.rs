@@ -16,12 +16,13 @@
 
 //! Eth Filter RPC implementation
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use anyhow::anyhow;
 use ethcore::{filter::Filter as EthcoreFilter, ids::BlockId};
+use ethereum_types::H256;
+use futures::sync::mpsc::UnboundedReceiver;
 use jsonrpc_core::{
-    futures::{future, prelude::*, stream},
+    futures::{future, prelude::*},
     BoxFuture, Result,
 };
 use lazy_static::lazy_static;
@@ -35,7 +36,10 @@ use parking_lot::Mutex;
 use prometheus::{labels, register_int_counter_vec, IntCounterVec};
 use slog::{info, Logger};
 
-use crate::{translator::Translator, util::jsonrpc_error};
+use crate::{
+    translator::{Translator, MAX_BLOCK_RANGE},
+    util::jsonrpc_error,
+};
 
 // Metrics.
 lazy_static! {
@@ -47,11 +51,16 @@ lazy_static! {
     .unwrap();
 }
 
+/// Queues of transactions which became pending since the last poll, keyed
+/// by pending transaction filter.
+type PendingQueues = HashMap<usize, UnboundedReceiver<H256>>;
+
 /// Eth filter rpc implementation for a full node.
 pub struct EthFilterClient {
     logger: Logger,
     translator: Arc<Translator>,
     polls: Arc<Mutex<PollManager<PollFilter>>>,
+    pending_queues: Arc<Mutex<PendingQueues>>,
 }
 
 impl EthFilterClient {
@@ -61,6 +70,48 @@ impl EthFilterClient {
             logger: get_logger("gateway/impls/eth_filter"),
             translator,
             polls: Arc::new(Mutex::new(PollManager::new())),
+            pending_queues: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+/// Drops the queues of pending transaction filters which were uninstalled or
+/// expired, so the pending pool stops feeding them.
+fn prune_pending_queues(polls: &mut PollManager<PollFilter>, queues: &mut PendingQueues) {
+    queues.retain(|id, _| polls.poll(id).is_some());
+}
+
+/// Takes all hashes which are ready in a pending transaction queue.
+///
+/// Must be called from within a task.
+fn drain_pending_queue(queue: &mut UnboundedReceiver<H256>) -> Vec<H256> {
+    let mut hashes = vec![];
+    while let Ok(Async::Ready(Some(hash))) = queue.poll() {
+        hashes.push(hash);
+    }
+    hashes
+}
+
+/// Block range which the next poll of a block filter reports, given the
+/// filter's next block and the latest block.
+///
+/// At most one block range is reported per poll, the rest is picked up by
+/// the following polls.
+fn block_filter_range(next: u64, latest: u64) -> Option<(u64, u64)> {
+    let to = latest.min(next.saturating_add(MAX_BLOCK_RANGE - 1));
+    if next > to {
+        None
+    } else {
+        Some((next, to))
+    }
+}
+
+/// Moves a block filter past the blocks `from` to `to` once they were
+/// reported, unless another poll already did.
+fn advance_block_filter(polls: &mut PollManager<PollFilter>, id: usize, from: u64, to: u64) {
+    if let Some(PollFilter::Block(ref mut number)) = polls.poll_mut(&id) {
+        if *number == from {
+            *number = to + 1;
         }
     }
 }
@@ -114,9 +165,13 @@ impl EthFilter for EthFilterClient {
             .with(&labels! {"call" => "newPendingTransactionFilter",})
             .inc();
 
-        // We don't have pending transactions, so this is a no-op filter.
+        // Only transactions which become pending from now on are reported.
+        let queue = self.translator.pending_pool().subscribe();
         let mut polls = self.polls.lock();
+        let mut queues = self.pending_queues.lock();
         let id = polls.create_poll(PollFilter::PendingTransaction(vec![]));
+        queues.insert(id, queue);
+        prune_pending_queues(&mut polls, &mut queues);
         Ok(id.into())
     }
 
@@ -126,6 +181,7 @@ impl EthFilter for EthFilterClient {
             .inc();
 
         let polls = self.polls.clone();
+        let pending_queues = self.pending_queues.clone();
         let translator = self.translator.clone();
 
         Box::new(
@@ -133,30 +189,51 @@ impl EthFilter for EthFilterClient {
                 .get_latest_block()
                 .map_err(jsonrpc_error)
                 .and_then(move |blk| -> BoxFuture<FilterChanges> {
+                    let cursor_polls = polls.clone();
                     let mut polls = polls.lock();
                     match polls.poll_mut(&index.value()) {
                         None => Box::new(future::err(errors::filter_not_found())),
-                        Some(PollFilter::Block(ref mut number)) => {
-                            // TODO: Should we support block range fetch?
-                            let updates = Box::new(
-                                stream::iter_ok(*number..=blk.number_u64())
-                                    .and_then(move |round| translator.get_block_by_round(round))
-                                    .and_then(|blk| match blk {
-                                        Some(blk) => Ok(blk),
-                                        None => Err(anyhow!("block not found")),
-                                    })
-                                    .map(|blk| RpcH256::from(blk.hash()))
-                                    .collect()
-                                    .map_err(jsonrpc_error)
-                                    .map(FilterChanges::Hashes),
-                            );
+                        Some(PollFilter::Block(ref number)) => {
+                            let (from, to) = match block_filter_range(*number, blk.number_u64()) {
+                                Some(range) => range,
+                                None => return Box::new(future::ok(FilterChanges::Hashes(vec![]))),
+                            };
 
-                            *number = blk.number_u64();
-                            updates
+                            Box::new(
+                                translator
+                                    .get_blocks_by_range(from, to)
+                                    .map(move |blks| {
+                                        // Only move on once the blocks were fetched, so
+                                        // that the range is retried after a failure.
+                                        advance_block_filter(
+                                            &mut cursor_polls.lock(),
+                                            index.value(),
+                                            from,
+                                            to,
+                                        );
+
+                                        FilterChanges::Hashes(
+                                            blks.iter()
+                                                .map(|blk| RpcH256::from(blk.hash()))
+                                                .collect(),
+                                        )
+                                    })
+                                    .map_err(jsonrpc_error),
+                            )
                         }
                         Some(PollFilter::PendingTransaction(_)) => {
-                            // We don't have pending transactions, so this is a no-op filter.
-                            Box::new(future::ok(FilterChanges::Hashes(vec![])))
+                            // Report the transactions which became pending since the last
+                            // poll, even if they are no longer pending.
+                            let mut queues = pending_queues.lock();
+                            let hashes = match queues.get_mut(&index.value()) {
+                                Some(queue) => drain_pending_queue(queue),
+                                None => vec![],
+                            };
+                            prune_pending_queues(&mut polls, &mut queues);
+
+                            Box::new(future::ok(FilterChanges::Hashes(
+                                hashes.into_iter().map(Into::into).collect(),
+                            )))
                         }
                         Some(PollFilter::Logs(ref mut block_number, _, ref filter)) => {
                             // Build appropriate filter.
@@ -219,6 +296,80 @@ impl EthFilter for EthFilterClient {
             .with(&labels! {"call" => "uninstallFilter",})
             .inc();
 
-        Ok(self.polls.lock().remove_poll(&index.value()))
+        let mut polls = self.polls.lock();
+        self.pending_queues.lock().remove(&index.value());
+        Ok(polls.remove_poll(&index.value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethcore::transaction::{Action, Transaction};
+    use ethereum_types::{Address, U256};
+
+    use super::*;
+    use crate::pending::PendingPool;
+
+    #[test]
+    fn test_drain_pending_queue() {
+        let pool = PendingPool::new();
+        let mut queue = pool.subscribe();
+        let hashes: Vec<H256> = (0..3)
+            .map(|nonce| {
+                let transaction = Transaction {
+                    nonce: nonce.into(),
+                    gas_price: U256::zero(),
+                    gas: 21000.into(),
+                    action: Action::Call(Address::zero()),
+                    value: U256::zero(),
+                    data: vec![],
+                }
+                .fake_sign(Address::from(1));
+                let hash = transaction.hash();
+                pool.insert(transaction);
+                // Transactions which finish before the poll are still reported.
+                pool.finish(&hash, Err("invalid nonce".to_owned()));
+                hash
+            })
+            .collect();
+
+        future::lazy(move || {
+            assert_eq!(drain_pending_queue(&mut queue), hashes);
+            assert!(drain_pending_queue(&mut queue).is_empty());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_block_filter_range() {
+        assert_eq!(block_filter_range(5, 10), Some((5, 10)));
+        assert_eq!(block_filter_range(10, 10), Some((10, 10)));
+        assert_eq!(block_filter_range(11, 10), None);
+        assert_eq!(block_filter_range(1, 1000), Some((1, MAX_BLOCK_RANGE)));
+    }
+
+    #[test]
+    fn test_advance_block_filter() {
+        let mut polls = PollManager::new();
+        let id = polls.create_poll(PollFilter::Block(5));
+        let next = |polls: &mut PollManager<PollFilter>| match polls.poll_mut(&id) {
+            Some(PollFilter::Block(number)) => *number,
+            _ => panic!("block filter should exist"),
+        };
+
+        // A failed fetch does not advance the filter, so the next poll
+        // reports the same range again.
+        assert_eq!(block_filter_range(next(&mut polls), 7), Some((5, 7)));
+        assert_eq!(block_filter_range(next(&mut polls), 7), Some((5, 7)));
+
+        advance_block_filter(&mut polls, id, 5, 7);
+        assert_eq!(next(&mut polls), 8);
+        assert_eq!(block_filter_range(next(&mut polls), 7), None);
+
+        // A fetch which finishes after another poll moved on is ignored.
+        advance_block_filter(&mut polls, id, 5, 7);
+        assert_eq!(next(&mut polls), 8);
     }
 }
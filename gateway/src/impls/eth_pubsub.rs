@@ -18,14 +18,27 @@
 
 //! Eth PUB-SUB rpc implementation.
 
-use std::sync::{Arc, Weak};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Error};
 use ethcore::{
     filter::{Filter as EthFilter, TxEntry as EthTxEntry, TxFilter as EthTxFilter},
     ids::BlockId,
+    log_entry::LocalizedLogEntry,
+};
+use ethereum_types::H256;
+use futures::{
+    future::{self, Either, Loop},
+    prelude::*,
+    stream,
+    sync::mpsc::{channel, unbounded, Sender, UnboundedSender},
 };
-use futures::{prelude::*, stream};
 use jsonrpc_core::Result;
 use jsonrpc_macros::{
     pubsub::{Sink, Subscriber},
@@ -40,12 +53,18 @@ use parity_rpc::v1::{
     traits::EthPubSub,
     types::{pubsub, TransactionOutcome},
 };
-use parking_lot::RwLock;
-use prometheus::{labels, register_int_counter_vec, IntCounterVec};
-use slog::{error, info, warn, Logger};
-use tokio::spawn;
+use parking_lot::{Mutex, RwLock};
+use prometheus::{
+    labels, register_int_counter, register_int_counter_vec, register_int_gauge_vec, IntCounter,
+    IntCounterVec, IntGaugeVec,
+};
+use slog::{info, warn, Logger};
+use tokio::{spawn, timer::Delay};
 
-use crate::{pubsub::Listener, translator::Translator};
+use crate::{
+    pubsub::Listener,
+    translator::{Translator, MAX_BLOCK_RANGE},
+};
 
 // Metrics.
 lazy_static! {
@@ -55,10 +74,52 @@ lazy_static! {
         &["call"]
     )
     .unwrap();
+    static ref ETH_PUBSUB_STALLED_RANGES: IntGaugeVec = register_int_gauge_vec!(
+        "web3_gateway_eth_pubsub_stalled_ranges",
+        "Number of block ranges whose notifications are held up as they could not be fetched yet",
+        &["kind"]
+    )
+    .unwrap();
+    static ref ETH_PUBSUB_DROPPED_LOG_SUBSCRIBERS: IntCounter = register_int_counter!(
+        "web3_gateway_eth_pubsub_dropped_log_subscribers",
+        "Number of logs subscribers dropped as they did not keep up with their logs"
+    )
+    .unwrap();
 }
 
+/// Delay before fetching the blocks or logs of a block range again after a
+/// failure.
+const RANGE_FETCH_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for the delay between attempts to fetch the blocks or logs of
+/// a block range.
+const RANGE_FETCH_MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Number of block ranges whose logs can be queued for a subscriber before
+/// it is dropped for not keeping up.
+const LOG_QUEUE_SIZE: usize = 256;
+
 type PubSubClient = Sink<pubsub::Result>;
 
+/// Subscriber which log notifications are sent to.
+trait LogSink: Send + 'static {
+    fn notify_log(
+        &self,
+        log: LocalizedLogEntry,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send>;
+}
+
+impl LogSink for PubSubClient {
+    fn notify_log(
+        &self,
+        log: LocalizedLogEntry,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        Box::new(
+            self.notify(Ok(pubsub::Result::Log(log.into())))
+                .map(|_| ())
+                .map_err(|err| anyhow!("{:?}", err)),
+        )
+    }
+}
+
 /// Eth PubSub implementation.
 pub struct EthPubSubClient {
     logger: Logger,
@@ -66,6 +127,7 @@ pub struct EthPubSubClient {
     heads_subscribers: Arc<RwLock<Subscribers<PubSubClient>>>,
     logs_subscribers: Arc<RwLock<Subscribers<(PubSubClient, EthFilter)>>>,
     tx_subscribers: Arc<RwLock<Subscribers<(PubSubClient, EthTxFilter)>>>,
+    pending_subscribers: Arc<RwLock<Subscribers<PubSubClient>>>,
 }
 
 impl EthPubSubClient {
@@ -74,6 +136,7 @@ impl EthPubSubClient {
         let heads_subscribers = Arc::new(RwLock::new(Subscribers::default()));
         let logs_subscribers = Arc::new(RwLock::new(Subscribers::default()));
         let tx_subscribers = Arc::new(RwLock::new(Subscribers::default()));
+        let pending_subscribers = Arc::new(RwLock::new(Subscribers::default()));
         let logger = get_logger("gateway/impls/eth_pubsub");
 
         EthPubSubClient {
@@ -84,10 +147,13 @@ impl EthPubSubClient {
                 heads_subscribers: heads_subscribers.clone(),
                 logs_subscribers: logs_subscribers.clone(),
                 tx_subscribers: tx_subscribers.clone(),
+                pending_subscribers: pending_subscribers.clone(),
+                log_ranges: Mutex::new(None),
             }),
             heads_subscribers,
             logs_subscribers,
             tx_subscribers,
+            pending_subscribers,
         }
     }
 
@@ -104,6 +170,9 @@ pub struct ChainNotificationHandler {
     heads_subscribers: Arc<RwLock<Subscribers<PubSubClient>>>,
     logs_subscribers: Arc<RwLock<Subscribers<(PubSubClient, EthFilter)>>>,
     tx_subscribers: Arc<RwLock<Subscribers<(PubSubClient, EthTxFilter)>>>,
+    pending_subscribers: Arc<RwLock<Subscribers<PubSubClient>>>,
+    /// Block ranges whose logs are yet to be notified, started on first use.
+    log_ranges: Mutex<Option<UnboundedSender<(u64, u64)>>>,
 }
 
 impl ChainNotificationHandler {
@@ -123,61 +192,323 @@ impl ChainNotificationHandler {
             return;
         }
 
-        // TODO: Should we support block range fetch?
         let heads_subscribers = self.heads_subscribers.clone();
+        let wanted_subscribers = self.heads_subscribers.clone();
         let translator = self.translator.clone();
         let logger = self.logger.clone();
         let logger2 = self.logger.clone();
         spawn(
-            stream::iter_ok(from_block..=to_block)
-                .and_then(move |round| translator.get_block_by_round(round))
-                .and_then(|blk| match blk {
-                    Some(blk) => Ok(blk),
-                    None => Err(anyhow!("block not found")),
+            stream::iter_ok((from_block..=to_block).step_by(MAX_BLOCK_RANGE as usize))
+                .and_then(move |from| {
+                    let to = to_block.min(from + MAX_BLOCK_RANGE - 1);
+                    let translator = translator.clone();
+                    let wanted_subscribers = wanted_subscribers.clone();
+                    // Retry ranges which cannot be fetched, so that no heads
+                    // are missed and they are notified in order.
+                    retry_range(
+                        logger.clone(),
+                        "heads",
+                        (from, to),
+                        RANGE_FETCH_INITIAL_BACKOFF,
+                        move || !wanted_subscribers.read().is_empty(),
+                        move || translator.get_blocks_by_range(from, to),
+                    )
                 })
-                .map(|blk| blk.rich_header())
-                .collect()
-                .map_err(move |err| error!(logger, "Failed to fetch blocks for heads notify"; "err" => ?err))
-                .map(move |headers| {
+                .for_each(move |blks| {
                     let subscribers = heads_subscribers.read();
 
-                    for header in headers {
+                    for blk in blks.unwrap_or_default() {
+                        let header = blk.rich_header();
                         for subscriber in subscribers.values() {
-                            Self::notify(&logger2, subscriber, pubsub::Result::Header(header.clone()));
+                            Self::notify(
+                                &logger2,
+                                subscriber,
+                                pubsub::Result::Header(header.clone()),
+                            );
                         }
                     }
+
+                    Ok(())
                 }),
         );
     }
 
     fn notify_logs(&self, from_block: u64, to_block: u64) {
-        for &(ref subscriber, ref filter) in self.logs_subscribers.read().values() {
-            let mut filter = filter.clone();
-
-            // Limit query range.
-            filter.from_block = BlockId::Number(from_block);
-            filter.to_block = BlockId::Number(to_block);
-
-            let subscriber = subscriber.clone();
-            let logger = self.logger.clone();
-            let logger2 = self.logger.clone();
-
-            spawn(
-                self.translator
-                    .logs(filter)
-                    .map(move |logs| {
-                        for log in logs {
-                            Self::notify(&logger2, &subscriber, pubsub::Result::Log(log.into()));
-                        }
-                    })
-                    .map_err(move |err| {
-                        error!(logger, "Failed to fetch logs";
-                            "err" => ?err,
-                        );
-                    }),
+        // If there are no subscribers, don't do any notification processing.
+        if self.logs_subscribers.read().is_empty() {
+            return;
+        }
+
+        let mut log_ranges = self.log_ranges.lock();
+        for from in (from_block..=to_block).step_by(MAX_BLOCK_RANGE as usize) {
+            let range = (from, to_block.min(from + MAX_BLOCK_RANGE - 1));
+            let range = match log_ranges
+                .as_ref()
+                .map(|sender| sender.unbounded_send(range))
+            {
+                Some(Ok(())) => continue,
+                Some(Err(err)) => {
+                    warn!(self.logger, "Log notifier stopped, restarting it");
+                    err.into_inner()
+                }
+                None => range,
+            };
+
+            let sender = self.start_log_notifier();
+            // The receiver was just created, so it cannot be gone yet.
+            let _ = sender.unbounded_send(range);
+            *log_ranges = Some(sender);
+        }
+    }
+
+    /// Starts the task which fetches the logs for logs subscribers, one block
+    /// range at a time.
+    ///
+    /// The logs of each range are fetched once for all subscribers and then
+    /// queued for each subscriber separately, so that subscribers receive
+    /// their logs in order without waiting for each other. Blocks are final,
+    /// so logs are never removed.
+    ///
+    /// A range which cannot be fetched is retried until it succeeds, holding
+    /// up the following ranges, so that no logs are missed. Subscribers whose
+    /// queue fills up are dropped.
+    fn start_log_notifier(&self) -> UnboundedSender<(u64, u64)> {
+        let (sender, receiver) = unbounded();
+        let translator = self.translator.clone();
+        let logs_subscribers = self.logs_subscribers.clone();
+        let logger = self.logger.clone();
+
+        spawn(
+            receiver
+                .fold(HashMap::new(), move |mut queues, (from_block, to_block)| {
+                    let subscribers: Vec<_> = logs_subscribers
+                        .read()
+                        .iter()
+                        .map(|(id, subscriber)| (id.clone(), subscriber.clone()))
+                        .collect();
+                    // Stop the queues of subscribers which are gone.
+                    queues.retain(|id, _| subscribers.iter().any(|&(ref other, _)| other == id));
+                    if subscribers.is_empty() {
+                        return Either::A(future::ok::<_, ()>(queues));
+                    }
+
+                    let mut filter =
+                        union_filter(subscribers.iter().map(|&(_, (_, ref filter))| filter));
+                    filter.from_block = BlockId::Number(from_block);
+                    filter.to_block = BlockId::Number(to_block);
+
+                    let logger = logger.clone();
+                    let translator = translator.clone();
+                    let wanted_subscribers = logs_subscribers.clone();
+                    let dropped_subscribers = logs_subscribers.clone();
+                    Either::B(
+                        retry_range(
+                            logger.clone(),
+                            "logs",
+                            (from_block, to_block),
+                            RANGE_FETCH_INITIAL_BACKOFF,
+                            move || !wanted_subscribers.read().is_empty(),
+                            move || translator.logs(filter.clone()),
+                        )
+                        .map(move |logs| {
+                            if let Some(logs) = logs {
+                                let dropped = queue_logs(&logger, &mut queues, subscribers, &logs);
+                                let mut subscribers = dropped_subscribers.write();
+                                for id in dropped {
+                                    subscribers.remove(&id);
+                                }
+                            }
+                            queues
+                        }),
+                    )
+                })
+                .map(|_| ()),
+        );
+
+        sender
+    }
+}
+
+/// Queues the logs of a block range for each subscriber whose filter matches
+/// any of them, starting the queue of the subscriber if needed.
+///
+/// Returns the subscribers whose queue is full, which are to be dropped.
+fn queue_logs<I, S>(
+    logger: &Logger,
+    queues: &mut HashMap<I, Sender<Vec<LocalizedLogEntry>>>,
+    subscribers: Vec<(I, (S, EthFilter))>,
+    logs: &[LocalizedLogEntry],
+) -> Vec<I>
+where
+    I: Eq + Hash + Clone + Debug,
+    S: LogSink + Clone,
+{
+    let mut dropped = vec![];
+    for (id, (subscriber, filter)) in subscribers {
+        let logs: Vec<_> = logs
+            .iter()
+            .filter(|log| filter.matches(&log.entry))
+            .cloned()
+            .collect();
+        if logs.is_empty() {
+            continue;
+        }
+
+        let full = match queues
+            .entry(id.clone())
+            .or_insert_with(|| start_log_queue(logger, subscriber))
+            .try_send(logs)
+        {
+            Ok(()) => continue,
+            Err(err) => err.is_full(),
+        };
+        queues.remove(&id);
+        if full {
+            warn!(logger, "Logs subscriber does not keep up, dropping it";
+                "subscriber" => ?id,
             );
+            ETH_PUBSUB_DROPPED_LOG_SUBSCRIBERS.inc();
+            dropped.push(id);
         }
     }
+    dropped
+}
+
+/// Starts the task which sends the queued logs to a subscriber, each after
+/// the previous one was sent. At most `LOG_QUEUE_SIZE` block ranges are
+/// queued.
+fn start_log_queue<S: LogSink + Clone>(
+    logger: &Logger,
+    subscriber: S,
+) -> Sender<Vec<LocalizedLogEntry>> {
+    let (sender, receiver) = channel::<Vec<LocalizedLogEntry>>(LOG_QUEUE_SIZE);
+    let logger = logger.clone();
+
+    spawn(receiver.for_each(move |logs| {
+        let logger = logger.clone();
+        let subscriber = subscriber.clone();
+        stream::iter_ok(logs)
+            .for_each(move |log| subscriber.notify_log(log))
+            // A failed subscriber must not stop its queue, it is dropped
+            // once the subscriber is gone.
+            .then(move |result| {
+                if let Err(err) = result {
+                    warn!(logger, "Unable to send notification"; "err" => ?err);
+                }
+                Ok(())
+            })
+    }));
+
+    sender
+}
+
+/// Fetches the blocks or logs of a block range, retrying with exponential
+/// backoff capped at `RANGE_FETCH_MAX_BACKOFF` until it succeeds.
+///
+/// Gives up with `None` once `wanted` returns false before an attempt, e.g.,
+/// because there are no subscribers left. Ranges are counted as stalled
+/// while they are being retried.
+fn retry_range<T, R, F, W>(
+    logger: Logger,
+    kind: &'static str,
+    (from_block, to_block): (u64, u64),
+    initial_backoff: Duration,
+    wanted: W,
+    fetch: F,
+) -> impl Future<Item = Option<T>, Error = ()>
+where
+    T: Send + 'static,
+    R: Future<Item = T, Error = Error> + Send + 'static,
+    F: Fn() -> R + Send + 'static,
+    W: Fn() -> bool + Send + 'static,
+{
+    let stalled = ETH_PUBSUB_STALLED_RANGES.with(&labels! {"kind" => kind,});
+    future::loop_fn((initial_backoff, false), move |(backoff, retried)| {
+        let done = {
+            let stalled = stalled.clone();
+            move |items| {
+                if retried {
+                    stalled.dec();
+                }
+                Loop::Break(items)
+            }
+        };
+        if !wanted() {
+            return Either::A(future::ok(done(None)));
+        }
+
+        let logger = logger.clone();
+        let stalled = stalled.clone();
+        Either::B(fetch().then(move |result| match result {
+            Ok(items) => Either::A(future::ok(done(Some(items)))),
+            Err(err) => {
+                warn!(logger, "Failed to fetch block range, retrying";
+                    "kind" => kind,
+                    "err" => ?err,
+                    "from_block" => from_block,
+                    "to_block" => to_block,
+                    "backoff" => ?backoff,
+                );
+                if !retried {
+                    stalled.inc();
+                }
+                Either::B(
+                    Delay::new(Instant::now() + backoff)
+                        .map_err(|_| ())
+                        .map(move |()| {
+                            Loop::Continue(((backoff * 2).min(RANGE_FETCH_MAX_BACKOFF), true))
+                        }),
+                )
+            }
+        }))
+    })
+}
+
+/// Returns a filter which matches at least the logs matched by any of the
+/// given filters, so that their logs can be fetched with a single query.
+fn union_filter<'a, I>(filters: I) -> EthFilter
+where
+    I: IntoIterator<Item = &'a EthFilter>,
+{
+    let mut union = EthFilter {
+        from_block: BlockId::Latest,
+        to_block: BlockId::Latest,
+        address: Some(vec![]),
+        topics: vec![Some(vec![]); 4],
+        limit: None,
+    };
+
+    for filter in filters {
+        // An unrestricted address or topic in any filter leaves the union
+        // unrestricted there as well.
+        union.address = match (union.address.take(), &filter.address) {
+            (Some(mut addresses), &Some(ref other)) => {
+                addresses.extend(other.iter().cloned());
+                Some(addresses)
+            }
+            _ => None,
+        };
+        for (index, topics) in union.topics.iter_mut().enumerate() {
+            *topics = match (topics.take(), filter.topics.get(index)) {
+                (Some(mut topics), Some(&Some(ref other))) => {
+                    topics.extend(other.iter().cloned());
+                    Some(topics)
+                }
+                _ => None,
+            };
+        }
+    }
+
+    if let Some(ref mut addresses) = union.address {
+        addresses.sort();
+        addresses.dedup();
+    }
+    for topics in union.topics.iter_mut().filter_map(Option::as_mut) {
+        topics.sort();
+        topics.dedup();
+    }
+
+    union
 }
 
 impl Listener for ChainNotificationHandler {
@@ -202,6 +533,16 @@ impl Listener for ChainNotificationHandler {
             );
         }
     }
+
+    fn notify_pending_transaction(&self, hash: H256) {
+        for subscriber in self.pending_subscribers.read().values() {
+            Self::notify(
+                &self.logger,
+                subscriber,
+                pubsub::Result::TransactionHash(hash.into()),
+            );
+        }
+    }
 }
 
 impl EthPubSub for EthPubSubClient {
@@ -243,8 +584,13 @@ impl EthPubSub for EthPubSubClient {
                 self.tx_subscribers.write().push(subscriber, filter.into());
                 return;
             }
-            // we don't track pending transactions currently
-            (pubsub::Kind::NewPendingTransactions, _) => errors::unimplemented(None),
+            (pubsub::Kind::NewPendingTransactions, None) => {
+                self.pending_subscribers.write().push(subscriber);
+                return;
+            }
+            (pubsub::Kind::NewPendingTransactions, _) => {
+                errors::invalid_params("newPendingTransactions", "Expected no parameters.")
+            }
             _ => errors::unimplemented(None),
         };
 
@@ -260,7 +606,230 @@ impl EthPubSub for EthPubSubClient {
         let res = self.heads_subscribers.write().remove(&id).is_some();
         let res2 = self.logs_subscribers.write().remove(&id).is_some();
         let res3 = self.tx_subscribers.write().remove(&id).is_some();
+        let res4 = self.pending_subscribers.write().remove(&id).is_some();
+
+        Ok(res || res2 || res3 || res4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use ethcore::log_entry::LogEntry;
+    use ethereum_types::Address;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    /// Sink which forwards the logs sent to it, or fails if it is closed.
+    #[derive(Clone)]
+    struct StubSink {
+        logs: Option<mpsc::Sender<LocalizedLogEntry>>,
+    }
+
+    impl LogSink for StubSink {
+        fn notify_log(
+            &self,
+            log: LocalizedLogEntry,
+        ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+            match self.logs {
+                Some(ref logs) => {
+                    logs.send(log).unwrap();
+                    Box::new(future::ok(()))
+                }
+                None => Box::new(future::err(anyhow!("sink closed"))),
+            }
+        }
+    }
+
+    /// Sink which never finishes sending a log.
+    #[derive(Clone)]
+    struct StuckSink;
+
+    impl LogSink for StuckSink {
+        fn notify_log(
+            &self,
+            _log: LocalizedLogEntry,
+        ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+            Box::new(future::empty())
+        }
+    }
+
+    fn log(block_number: u64) -> LocalizedLogEntry {
+        LocalizedLogEntry {
+            entry: LogEntry {
+                address: Address::from(1),
+                topics: vec![],
+                data: vec![],
+            },
+            block_hash: H256::zero(),
+            block_number,
+            transaction_hash: H256::zero(),
+            transaction_index: 0,
+            transaction_log_index: 0,
+            log_index: 0,
+        }
+    }
+
+    fn filter(address: Option<Vec<Address>>, topics: Vec<Option<Vec<H256>>>) -> EthFilter {
+        EthFilter {
+            from_block: BlockId::Latest,
+            to_block: BlockId::Latest,
+            address,
+            topics,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_retry_range() {
+        let attempts = Arc::new(Mutex::new(0));
+        let fetch = {
+            let attempts = attempts.clone();
+            move || {
+                let mut attempts = attempts.lock();
+                *attempts += 1;
+                if *attempts < 3 {
+                    future::err(anyhow!("node unavailable"))
+                } else {
+                    future::ok(*attempts)
+                }
+            }
+        };
+        let backoff = Duration::from_millis(1);
+        let logger = get_logger("gateway/impls/eth_pubsub/test");
+        let mut runtime = Runtime::new().unwrap();
+
+        // Failed attempts are retried until the range is fetched.
+        let result = runtime.block_on(retry_range(
+            logger.clone(),
+            "test",
+            (1, 2),
+            backoff,
+            || true,
+            fetch.clone(),
+        ));
+        assert_eq!(result, Ok(Some(3)));
+        // The range is no longer counted as stalled once it was fetched.
+        assert_eq!(
+            ETH_PUBSUB_STALLED_RANGES
+                .with(&labels! {"kind" => "test",})
+                .get(),
+            0
+        );
+
+        // Nothing is fetched once the range is no longer wanted.
+        let result = runtime.block_on(retry_range(
+            logger,
+            "test",
+            (1, 2),
+            backoff,
+            || false,
+            fetch,
+        ));
+        assert_eq!(result, Ok(None));
+        assert_eq!(*attempts.lock(), 3);
+    }
+
+    #[test]
+    fn test_union_filter() {
+        let union = union_filter(&[
+            filter(
+                Some(vec![Address::from(2), Address::from(1)]),
+                vec![Some(vec![H256::from(1)]), Some(vec![H256::from(2)])],
+            ),
+            filter(
+                Some(vec![Address::from(1)]),
+                vec![Some(vec![H256::from(3)])],
+            ),
+        ]);
+        assert_eq!(
+            union.address,
+            Some(vec![Address::from(1), Address::from(2)])
+        );
+        assert_eq!(
+            union.topics,
+            vec![Some(vec![H256::from(1), H256::from(3)]), None, None, None]
+        );
+
+        // Any unrestricted filter makes the union unrestricted.
+        let union = union_filter(&[
+            filter(Some(vec![Address::from(1)]), vec![]),
+            filter(None, vec![]),
+        ]);
+        assert_eq!(union.address, None);
+        assert_eq!(union.topics, vec![None, None, None, None]);
+    }
+
+    #[test]
+    fn test_queue_logs_closed_sink() {
+        let logger = get_logger("gateway/impls/eth_pubsub/test");
+        let (sender, receiver) = mpsc::channel();
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(move || {
+                let subscribers = || {
+                    vec![
+                        (1, (StubSink { logs: None }, filter(None, vec![]))),
+                        (
+                            2,
+                            (
+                                StubSink {
+                                    logs: Some(sender.clone()),
+                                },
+                                filter(None, vec![]),
+                            ),
+                        ),
+                    ]
+                };
+                let mut queues = HashMap::new();
+                queue_logs(&logger, &mut queues, subscribers(), &[log(1), log(2)]);
+                queue_logs(&logger, &mut queues, subscribers(), &[log(3)]);
+                Ok::<_, ()>(())
+            }))
+            .unwrap();
+
+        // The open subscriber receives every log in order, although sending to
+        // the closed one fails.
+        let blocks: Vec<u64> = (0..3)
+            .map(|_| {
+                receiver
+                    .recv_timeout(Duration::from_secs(10))
+                    .expect("log should be notified")
+                    .block_number
+            })
+            .collect();
+        assert_eq!(blocks, vec![1, 2, 3]);
+
+        runtime.shutdown_on_idle().wait().unwrap();
+    }
+
+    #[test]
+    fn test_queue_logs_slow_subscriber() {
+        let logger = get_logger("gateway/impls/eth_pubsub/test");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(move || {
+                let subscribers = || vec![(1, (StuckSink, filter(None, vec![])))];
+                let mut queues = HashMap::new();
+                let mut dropped = vec![];
+                for block in 0..LOG_QUEUE_SIZE as u64 + 3 {
+                    let ids = queue_logs(&logger, &mut queues, subscribers(), &[log(block)]);
+                    if !ids.is_empty() {
+                        // The queue holds all ranges the subscriber can fall behind.
+                        assert!(block >= LOG_QUEUE_SIZE as u64);
+                        dropped.extend(ids);
+                        break;
+                    }
+                }
 
-        Ok(res || res2 || res3)
+                assert_eq!(dropped, vec![1]);
+                assert!(queues.is_empty());
+                Ok::<_, ()>(())
+            }))
+            .unwrap();
     }
 }
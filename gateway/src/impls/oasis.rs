@@ -1,18 +1,20 @@
 use std::sync::Arc;
 
+use anyhow::Result;
 use ethereum_types::Address;
 use futures::prelude::*;
 use hash::keccak;
 use io_context::Context;
-use jsonrpc_core::BoxFuture;
+use jsonrpc_core::{self, BoxFuture};
 use jsonrpc_macros::Trailing;
 use lazy_static::lazy_static;
 use oasis_core_keymanager_client::{KeyManagerClient, KeyPairId};
 use oasis_core_runtime::common::logger::get_logger;
+use oasis_ethwasi_runtime_common::{header::OasisHeader, wasm};
 use parity_rpc::v1::{
     helpers::errors,
     metadata::Metadata,
-    types::{BlockNumber, Bytes, H160 as RpcH160},
+    types::{BlockNumber, Bytes, H160 as RpcH160, H256 as RpcH256},
 };
 use prometheus::{
     labels, register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec,
@@ -20,7 +22,12 @@ use prometheus::{
 use slog::{debug, info, Logger};
 
 use crate::{
-    traits::oasis::{Oasis, RpcExecutionPayload, RpcPublicKeyPayload},
+    pending::{SubmissionState, SubmissionStatus},
+    traits::oasis::{
+        Oasis, RpcCodeKind, RpcContractInfo, RpcExecutionPayload, RpcOasisHeader,
+        RpcPublicKeyPayload, RpcSubmissionState, RpcTransactionStatus, RpcWasmExport,
+        RpcWasmImport,
+    },
     translator::Translator,
     util::{block_number_to_id, execution_error, jsonrpc_error},
 };
@@ -42,6 +49,66 @@ lazy_static! {
     .unwrap();
 }
 
+/// Converts the submission status of a transaction.
+fn transaction_status(status: SubmissionStatus) -> RpcTransactionStatus {
+    let (state, error) = match status.state {
+        SubmissionState::Pending => (RpcSubmissionState::Pending, None),
+        SubmissionState::Executed => (RpcSubmissionState::Executed, None),
+        SubmissionState::Failed(error) => (RpcSubmissionState::Failed, Some(error)),
+    };
+
+    RpcTransactionStatus {
+        state,
+        retries: (status.retries as u64).into(),
+        error,
+    }
+}
+
+/// Describes the given contract code.
+fn contract_info(code: &[u8]) -> Result<RpcContractInfo> {
+    let (header, body) = OasisHeader::split(code)?;
+
+    let mut info = RpcContractInfo {
+        kind: RpcCodeKind::Evm,
+        code_hash: keccak(code).into(),
+        code_size: (code.len() as u64).into(),
+        header: header.map(|header| RpcOasisHeader {
+            version: header.version,
+            confidential: header.confidential,
+            expiry: header.expiry,
+        }),
+        imports: None,
+        exports: None,
+    };
+
+    if wasm::is_wasm(body) {
+        info.kind = RpcCodeKind::Wasm;
+
+        let (imports, exports) = wasm::interface(&wasm::parse(body)?);
+        info.imports = Some(
+            imports
+                .into_iter()
+                .map(|import| RpcWasmImport {
+                    module: import.module,
+                    name: import.field,
+                    kind: import.kind.to_owned(),
+                })
+                .collect(),
+        );
+        info.exports = Some(
+            exports
+                .into_iter()
+                .map(|export| RpcWasmExport {
+                    name: export.field,
+                    kind: export.kind.to_owned(),
+                })
+                .collect(),
+        );
+    }
+
+    Ok(info)
+}
+
 /// Eth rpc implementation
 pub struct OasisClient {
     logger: Logger,
@@ -141,4 +208,105 @@ impl Oasis for OasisClient {
                 }),
         )
     }
+
+    fn get_contract_info(
+        &self,
+        address: RpcH160,
+        num: Trailing<BlockNumber>,
+    ) -> BoxFuture<Option<RpcContractInfo>> {
+        OASIS_RPC_CALLS
+            .with(&labels! {"call" => "getContractInfo",})
+            .inc();
+        let address: Address = RpcH160::into(address);
+        let num = num.unwrap_or_default();
+
+        info!(
+            self.logger,
+            "oasis_getContractInfo";
+                "address" => ?address,
+                "num" => ?num
+        );
+
+        Box::new(
+            self.translator
+                .get_block_unwrap(block_number_to_id(num))
+                .and_then(move |blk| match blk.state()?.code(&address)? {
+                    Some(code) => Ok(Some(contract_info(&code)?)),
+                    None => Ok(None),
+                })
+                .map_err(jsonrpc_error),
+        )
+    }
+
+    fn get_transaction_status(
+        &self,
+        hash: RpcH256,
+    ) -> jsonrpc_core::Result<Option<RpcTransactionStatus>> {
+        OASIS_RPC_CALLS
+            .with(&labels! {"call" => "getTransactionStatus",})
+            .inc();
+        info!(self.logger, "oasis_getTransactionStatus"; "hash" => ?hash);
+
+        Ok(self
+            .translator
+            .get_submission_status(&hash.into())
+            .map(transaction_status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    #[test]
+    fn test_contract_info_evm() {
+        let code = hex::decode("3331600055").unwrap();
+        let info = contract_info(&code).unwrap();
+        assert_eq!(info.kind, RpcCodeKind::Evm);
+        assert!(info.header.is_none());
+        assert!(info.imports.is_none());
+    }
+
+    #[test]
+    fn test_contract_info_header() {
+        let code = OasisHeader::builder()
+            .confidential(true)
+            .expiry(1577836800)
+            .build()
+            .prepend_to(include_bytes!("../../../resources/erc20/erc20.wasm"));
+
+        let info = contract_info(&code).unwrap();
+        assert_eq!(info.kind, RpcCodeKind::Wasm);
+        let header = info.header.unwrap();
+        assert_eq!(header.version, 1);
+        assert!(header.confidential);
+        assert_eq!(header.expiry, Some(1577836800));
+        assert!(info
+            .exports
+            .unwrap()
+            .iter()
+            .any(|export| export.name == "main" && export.kind == "function"));
+
+        // Truncated header.
+        assert!(contract_info(&code[..10]).is_err());
+    }
+
+    #[test]
+    fn test_transaction_status() {
+        let status = transaction_status(SubmissionStatus {
+            state: SubmissionState::Failed("invalid nonce".to_owned()),
+            retries: 2,
+        });
+        assert_eq!(status.state, RpcSubmissionState::Failed);
+        assert_eq!(status.retries, 2.into());
+        assert_eq!(status.error, Some("invalid nonce".to_owned()));
+
+        let status = transaction_status(SubmissionStatus {
+            state: SubmissionState::Pending,
+            retries: 0,
+        });
+        assert_eq!(status.state, RpcSubmissionState::Pending);
+        assert_eq!(status.error, None);
+    }
 }
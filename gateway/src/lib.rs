@@ -38,10 +38,13 @@ extern crate jsonrpc_http_server;
 extern crate jsonrpc_pubsub;
 extern crate jsonrpc_ws_server;
 extern crate keccak_hash as hash;
+extern crate lru_cache;
 extern crate parity_reactor;
 extern crate parity_rpc;
 extern crate prometheus;
+extern crate rand;
 extern crate serde_bytes;
+extern crate serde_json;
 extern crate slog;
 extern crate tokio;
 extern crate tokio_threadpool;
@@ -53,10 +56,13 @@ extern crate oasis_core_runtime;
 extern crate oasis_ethwasi_runtime_api;
 extern crate oasis_ethwasi_runtime_common;
 
+mod cache;
 mod impls;
 mod informant;
 mod middleware;
+mod pending;
 mod pubsub;
+mod retry;
 mod rpc;
 mod rpc_apis;
 mod run;
@@ -71,12 +77,19 @@ use anyhow::Result;
 use clap::{value_t_or_exit, ArgMatches};
 use ethereum_types::U256;
 use grpcio::EnvBuilder;
-use oasis_core_client::{create_txn_api_client, Node, TxnClient};
+use oasis_core_client::{
+    create_txn_api_client, transaction::api::storage::StorageClient, Node, TxnClient,
+};
 use oasis_core_runtime::common::namespace::Namespace;
 use oasis_ethwasi_runtime_api::*;
+use oasis_ethwasi_runtime_common::SizeLimits;
 use serde_bytes::ByteBuf;
 
-pub use self::run::RunningGateway;
+pub use self::{
+    cache::CacheConfig,
+    retry::{RetryPolicy, RetryReason},
+    run::RunningGateway,
+};
 
 with_api! {
     create_txn_api_client!(EthereumRuntimeClient, api);
@@ -92,6 +105,10 @@ pub fn start(
     ws_max_connections: usize,
     ws_rate_limit: usize,
     gas_price: U256,
+    size_limits: SizeLimits,
+    async_submission: bool,
+    retry_policy: RetryPolicy,
+    cache_config: CacheConfig,
     jsonrpc_max_batch_size: usize,
 ) -> Result<RunningGateway> {
     let node_address = args.value_of("node-address").unwrap();
@@ -101,6 +118,7 @@ pub fn start(
     let node = Node::new(env.clone(), node_address);
     let txn_client = TxnClient::new(node.channel(), runtime_id, None);
     let client = EthereumRuntimeClient::new(txn_client);
+    let storage_client = StorageClient::new(node.channel());
     // TODO: Key manager MRENCLAVE.
     let km_client = Arc::new(oasis_core_keymanager_client::RemoteClient::new_grpc(
         runtime_id,
@@ -111,6 +129,7 @@ pub fn start(
 
     run::execute(
         client,
+        storage_client,
         km_client,
         pubsub_interval_secs,
         interface,
@@ -120,6 +139,10 @@ pub fn start(
         ws_max_connections,
         ws_rate_limit,
         gas_price,
+        size_limits,
+        async_submission,
+        retry_policy,
+        cache_config,
         jsonrpc_max_batch_size,
     )
 }
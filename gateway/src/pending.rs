@@ -0,0 +1,332 @@
+//! Pool of transactions which were submitted through the gateway, but whose
+//! results are not yet known.
+use std::collections::{HashMap, VecDeque};
+
+use ethcore::transaction::SignedTransaction;
+use ethereum_types::{Address, H256, U256};
+use futures::sync::{
+    mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use oasis_ethwasi_runtime_api::ExecutionResult;
+use parking_lot::{Mutex, RwLock};
+
+/// Number of finished submissions whose status is remembered.
+const MAX_FINISHED: usize = 4096;
+
+/// State of a transaction submitted through the gateway.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubmissionState {
+    /// The transaction is being submitted.
+    Pending,
+    /// The transaction was executed and its receipt is available.
+    Executed,
+    /// The transaction could not be executed.
+    Failed(String),
+}
+
+/// Status of a transaction submitted through the gateway.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmissionStatus {
+    /// Submission state.
+    pub state: SubmissionState,
+    /// Number of times the transaction was resubmitted because it did not
+    /// fit into a batch.
+    pub retries: usize,
+}
+
+/// Result of a submission, or the reason why it failed.
+pub type SubmissionResult = Result<ExecutionResult, String>;
+
+/// Outcome of adding a transaction to the pool.
+pub enum Insertion {
+    /// The transaction was added and should be submitted by the caller.
+    Added,
+    /// The transaction is already being submitted. Resolves to the result of
+    /// that submission.
+    InFlight(oneshot::Receiver<SubmissionResult>),
+}
+
+struct Entry {
+    transaction: SignedTransaction,
+    retries: usize,
+    waiters: Vec<oneshot::Sender<SubmissionResult>>,
+}
+
+/// Statuses of finished submissions, oldest first.
+#[derive(Default)]
+struct Finished {
+    statuses: HashMap<H256, SubmissionStatus>,
+    order: VecDeque<H256>,
+}
+
+/// Local pool of pending transactions, keyed by transaction hash.
+///
+/// The pool only knows about transactions submitted through this gateway.
+/// A transaction is removed once its result is known, at which point its
+/// receipt is available from the chain. The status of the most recently
+/// finished submissions is kept around so clients can find out why a
+/// transaction has no receipt.
+#[derive(Default)]
+pub struct PendingPool {
+    transactions: RwLock<HashMap<H256, Entry>>,
+    finished: Mutex<Finished>,
+    subscribers: Mutex<Vec<UnboundedSender<H256>>>,
+}
+
+impl PendingPool {
+    /// Creates an empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a transaction to the pool and notifies subscribers of its hash.
+    ///
+    /// If the transaction is already pending, it is not added again and the
+    /// caller can wait for the result of the submission in flight instead.
+    pub fn insert(&self, transaction: SignedTransaction) -> Insertion {
+        let hash = transaction.hash();
+        {
+            let mut transactions = self.transactions.write();
+            if let Some(entry) = transactions.get_mut(&hash) {
+                let (sender, receiver) = oneshot::channel();
+                entry.waiters.push(sender);
+                return Insertion::InFlight(receiver);
+            }
+            transactions.insert(
+                hash,
+                Entry {
+                    transaction,
+                    retries: 0,
+                    waiters: vec![],
+                },
+            );
+        }
+
+        self.subscribers
+            .lock()
+            .retain(|subscriber| subscriber.unbounded_send(hash).is_ok());
+        Insertion::Added
+    }
+
+    /// Records that a pending transaction is being resubmitted.
+    pub fn retry(&self, hash: &H256) {
+        if let Some(entry) = self.transactions.write().get_mut(hash) {
+            entry.retries += 1;
+        }
+    }
+
+    /// Removes a transaction from the pool, records the result of its
+    /// submission and passes it on to everyone waiting for it.
+    pub fn finish(&self, hash: &H256, result: SubmissionResult) {
+        let entry = match self.transactions.write().remove(hash) {
+            Some(entry) => entry,
+            None => return,
+        };
+        for waiter in entry.waiters {
+            // The waiter may have given up.
+            let _ = waiter.send(result.clone());
+        }
+        let retries = entry.retries;
+        let state = match result {
+            Ok(_) => SubmissionState::Executed,
+            Err(err) => SubmissionState::Failed(err),
+        };
+
+        let mut finished = self.finished.lock();
+        if finished
+            .statuses
+            .insert(*hash, SubmissionStatus { state, retries })
+            .is_none()
+        {
+            finished.order.push_back(*hash);
+        }
+        while finished.order.len() > MAX_FINISHED {
+            let oldest = finished.order.pop_front().unwrap();
+            finished.statuses.remove(&oldest);
+        }
+    }
+
+    /// Retrieves the submission status of a transaction.
+    pub fn status(&self, hash: &H256) -> Option<SubmissionStatus> {
+        if let Some(entry) = self.transactions.read().get(hash) {
+            return Some(SubmissionStatus {
+                state: SubmissionState::Pending,
+                retries: entry.retries,
+            });
+        }
+
+        self.finished.lock().statuses.get(hash).cloned()
+    }
+
+    /// Retrieves a pending transaction.
+    pub fn get(&self, hash: &H256) -> Option<SignedTransaction> {
+        self.transactions
+            .read()
+            .get(hash)
+            .map(|entry| entry.transaction.clone())
+    }
+
+    /// Nonce following the highest nonce of pending transactions sent by
+    /// `sender`, if there are any.
+    pub fn next_nonce(&self, sender: &Address) -> Option<U256> {
+        self.transactions
+            .read()
+            .values()
+            .map(|entry| &entry.transaction)
+            .filter(|transaction| transaction.sender() == *sender)
+            .map(|transaction| transaction.nonce + U256::one())
+            .max()
+    }
+
+    /// Returns a stream of hashes of transactions added to the pool from now on.
+    pub fn subscribe(&self) -> UnboundedReceiver<H256> {
+        let (sender, receiver) = unbounded();
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethcore::transaction::{Action, Transaction};
+    use futures::Stream;
+
+    use super::*;
+
+    fn transaction(sender: Address, nonce: u64) -> SignedTransaction {
+        Transaction {
+            nonce: nonce.into(),
+            gas_price: U256::zero(),
+            gas: 21000.into(),
+            action: Action::Call(Address::zero()),
+            value: U256::zero(),
+            data: vec![],
+        }
+        .fake_sign(sender)
+    }
+
+    fn result(status_code: u8) -> ExecutionResult {
+        ExecutionResult {
+            cumulative_gas_used: 21000.into(),
+            gas_used: 21000.into(),
+            log_bloom: Default::default(),
+            logs: vec![],
+            status_code,
+            output: vec![],
+        }
+    }
+
+    fn added(insertion: Insertion) -> bool {
+        match insertion {
+            Insertion::Added => true,
+            Insertion::InFlight(_) => false,
+        }
+    }
+
+    #[test]
+    fn test_pending_pool() {
+        let pool = PendingPool::new();
+        let subscription = pool.subscribe();
+        let alice = Address::from(1);
+        let bob = Address::from(2);
+
+        let first = transaction(alice, 4);
+        let second = transaction(alice, 5);
+        assert!(added(pool.insert(first.clone())));
+        assert!(added(pool.insert(second.clone())));
+        // Resubmissions are not announced again.
+        assert!(!added(pool.insert(first.clone())));
+
+        assert_eq!(pool.get(&first.hash()), Some(first.clone()));
+        assert_eq!(pool.get(&second.hash()), Some(second.clone()));
+        assert_eq!(pool.next_nonce(&alice), Some(6.into()));
+        assert_eq!(pool.next_nonce(&bob), None);
+
+        pool.finish(&second.hash(), Ok(result(1)));
+        assert_eq!(pool.get(&second.hash()), None);
+        assert_eq!(pool.next_nonce(&alice), Some(5.into()));
+
+        // The subscription ends once the pool is gone.
+        drop(pool);
+        let hashes: Vec<H256> = subscription.wait().map(Result::unwrap).collect();
+        assert_eq!(hashes, vec![first.hash(), second.hash()]);
+    }
+
+    #[test]
+    fn test_in_flight() {
+        let pool = PendingPool::new();
+        let executed = transaction(Address::from(1), 0);
+        let failed = transaction(Address::from(1), 1);
+        assert!(added(pool.insert(executed.clone())));
+        assert!(added(pool.insert(failed.clone())));
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| match pool.insert(executed.clone()) {
+                Insertion::InFlight(waiter) => waiter,
+                Insertion::Added => panic!("transaction should be in flight"),
+            })
+            .collect();
+        let failed_waiter = match pool.insert(failed.clone()) {
+            Insertion::InFlight(waiter) => waiter,
+            Insertion::Added => panic!("transaction should be in flight"),
+        };
+
+        pool.finish(&executed.hash(), Ok(result(0)));
+        pool.finish(&failed.hash(), Err("invalid nonce".to_owned()));
+        for waiter in waiters {
+            assert_eq!(waiter.wait().unwrap().unwrap().status_code, 0);
+        }
+        assert_eq!(failed_waiter.wait().unwrap().unwrap_err(), "invalid nonce");
+
+        // Once finished, the transaction can be submitted again.
+        assert!(added(pool.insert(executed.clone())));
+    }
+
+    #[test]
+    fn test_submission_status() {
+        let pool = PendingPool::new();
+        let executed = transaction(Address::from(1), 0);
+        let failed = transaction(Address::from(1), 1);
+
+        assert_eq!(pool.status(&executed.hash()), None);
+
+        pool.insert(executed.clone());
+        pool.insert(failed.clone());
+        pool.retry(&failed.hash());
+        assert_eq!(
+            pool.status(&failed.hash()),
+            Some(SubmissionStatus {
+                state: SubmissionState::Pending,
+                retries: 1,
+            })
+        );
+
+        pool.finish(&executed.hash(), Ok(result(1)));
+        pool.finish(&failed.hash(), Err("invalid nonce".to_owned()));
+        assert_eq!(
+            pool.status(&executed.hash()),
+            Some(SubmissionStatus {
+                state: SubmissionState::Executed,
+                retries: 0,
+            })
+        );
+        assert_eq!(
+            pool.status(&failed.hash()),
+            Some(SubmissionStatus {
+                state: SubmissionState::Failed("invalid nonce".to_owned()),
+                retries: 1,
+            })
+        );
+
+        // Only the most recently finished submissions are remembered.
+        for nonce in 2..(MAX_FINISHED as u64 + 3) {
+            let transaction = transaction(Address::from(1), nonce);
+            let hash = transaction.hash();
+            pool.insert(transaction);
+            pool.finish(&hash, Ok(result(1)));
+        }
+        assert_eq!(pool.status(&executed.hash()), None);
+        assert_eq!(pool.status(&failed.hash()), None);
+    }
+}
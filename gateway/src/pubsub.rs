@@ -1,21 +1,36 @@
 //! Pub/sub support.
 use std::{
-    process::abort,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, RwLock, Weak,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use anyhow::Error;
 use ethcore::filter::TxEntry;
-use futures::prelude::*;
+use ethereum_types::H256;
+use futures::{future, prelude::*};
+use lazy_static::lazy_static;
 use oasis_core_runtime::common::logger::get_logger;
-use slog::{error, Logger};
-use tokio::timer::Interval;
+use prometheus::{register_int_gauge, IntGauge};
+use slog::{error, info, warn, Logger};
+use tokio::timer::Delay;
 
 use crate::translator::Translator;
 
+// Metrics.
+lazy_static! {
+    static ref PUBSUB_HEALTHY: IntGauge = register_int_gauge!(
+        "web3_gateway_pubsub_healthy",
+        "Whether the pub/sub broker follows new blocks through the block watch"
+    )
+    .unwrap();
+}
+
+/// Maximum delay between attempts to watch new blocks.
+const MAX_WATCH_BACKOFF: Duration = Duration::from_secs(60);
+
 /// An actor listening to chain events.
 ///
 /// All notifications are delivered in a future task context.
@@ -23,28 +38,161 @@ pub trait Listener: Send + Sync {
     fn notify_blocks(&self, from_block: u64, to_block: u64);
 
     fn notify_completed_transaction(&self, entry: &TxEntry, output: Vec<u8>);
+
+    fn notify_pending_transaction(&self, hash: H256);
+}
+
+/// Source of the new blocks followed by the broker.
+trait BlockSource: Send + Sync + 'static {
+    /// Watch the numbers of new blocks as they are finalized.
+    fn watch(&self) -> Box<dyn Stream<Item = u64, Error = Error> + Send>;
+
+    /// Retrieve the number of the latest block.
+    fn latest(&self) -> Box<dyn Future<Item = u64, Error = Error> + Send>;
+}
+
+impl BlockSource for Translator {
+    fn watch(&self) -> Box<dyn Stream<Item = u64, Error = Error> + Send> {
+        Box::new(self.watch_blocks().map(|blk| blk.number_u64()))
+    }
+
+    fn latest(&self) -> Box<dyn Future<Item = u64, Error = Error> + Send> {
+        Box::new(self.get_latest_block().map(|blk| blk.number_u64()))
+    }
 }
 
 struct Inner {
     logger: Logger,
-    translator: Arc<Translator>,
     last_notified_block: AtomicU64,
+    healthy: AtomicBool,
     listeners: RwLock<Vec<Weak<dyn Listener>>>,
 }
 
+impl Inner {
+    fn new() -> Self {
+        Self {
+            logger: get_logger("gateway/pubsub/broker"),
+            last_notified_block: AtomicU64::new(0),
+            healthy: AtomicBool::new(true),
+            listeners: RwLock::new(vec![]),
+        }
+    }
+
+    /// Marks the broker as degraded after the block watch failed.
+    ///
+    /// Listeners are only notified of new blocks when they are polled until
+    /// the block watch is back.
+    fn set_degraded(&self) {
+        if self.healthy.swap(false, Ordering::SeqCst) {
+            PUBSUB_HEALTHY.set(0);
+            warn!(self.logger, "Pub/sub broker degraded, polling for new blocks";
+                "last_notified_block" => self.last_notified_block.load(Ordering::SeqCst),
+            );
+        }
+    }
+
+    /// Marks the broker as healthy once the block watch delivers blocks again.
+    fn set_healthy(&self, latest_block: u64) {
+        if !self.healthy.swap(true, Ordering::SeqCst) {
+            PUBSUB_HEALTHY.set(1);
+            info!(self.logger, "Pub/sub broker recovered";
+                "last_notified_block" => self.last_notified_block.load(Ordering::SeqCst),
+                "latest_block" => latest_block,
+            );
+        }
+    }
+
+    /// Notifies all listeners of the blocks since the last notified block,
+    /// up to and including `to`.
+    fn notify_blocks(&self, to: u64) {
+        let last_notified_block = self.last_notified_block.load(Ordering::SeqCst);
+
+        // If there are no new blocks, return early.
+        if to <= last_notified_block {
+            return;
+        }
+
+        let from = last_notified_block + 1;
+        let listeners = self.listeners.read().unwrap();
+        for listener in listeners.iter() {
+            if let Some(listener) = listener.upgrade() {
+                listener.notify_blocks(from, to);
+            }
+        }
+
+        self.last_notified_block.store(to, Ordering::SeqCst);
+    }
+}
+
+/// Follows new blocks through the block watch of `source` and notifies the
+/// listeners of them.
+///
+/// Whenever the watch fails, the latest block is polled once and the watch
+/// is started again. Blocks missed in between are notified together with the
+/// next new block. The delay before each new attempt starts at `interval`
+/// and doubles while the watch keeps failing without delivering any blocks.
+fn follow_blocks<S: BlockSource>(
+    inner: Arc<Inner>,
+    source: Arc<S>,
+    interval: Duration,
+) -> impl Future<Item = (), Error = ()> {
+    future::loop_fn((inner, interval), move |(inner, backoff)| {
+        let source = source.clone();
+        let received = Arc::new(AtomicBool::new(false));
+        let watch_inner = inner.clone();
+        let watch_received = received.clone();
+
+        source
+            .watch()
+            .for_each(move |number| {
+                watch_received.store(true, Ordering::SeqCst);
+                watch_inner.set_healthy(number);
+                watch_inner.notify_blocks(number);
+                Ok(())
+            })
+            .then(move |result| {
+                match result {
+                    Ok(()) => warn!(inner.logger, "Block watch ended"),
+                    Err(err) => warn!(inner.logger, "Block watch failed";
+                        "err" => ?err,
+                    ),
+                }
+                inner.set_degraded();
+
+                let backoff = if received.load(Ordering::SeqCst) {
+                    interval
+                } else {
+                    backoff
+                };
+                let poll_inner = inner.clone();
+                Delay::new(Instant::now() + backoff)
+                    .map_err(Into::into)
+                    .and_then(move |()| source.latest())
+                    .then(move |result| {
+                        match result {
+                            Ok(number) => poll_inner.notify_blocks(number),
+                            Err(err) => error!(poll_inner.logger, "Failed to poll latest block";
+                                "err" => ?err,
+                            ),
+                        }
+
+                        let next_backoff = (backoff * 2).min(MAX_WATCH_BACKOFF.max(interval));
+                        Ok(future::Loop::<(), _>::Continue((poll_inner, next_backoff)))
+                    })
+            })
+    })
+}
+
 pub struct Broker {
     inner: Arc<Inner>,
+    translator: Arc<Translator>,
 }
 
 impl Broker {
     pub fn new(translator: Arc<Translator>) -> Self {
         Self {
-            inner: Arc::new(Inner {
-                logger: get_logger("gateway/pubsub/broker"),
-                translator,
-                last_notified_block: AtomicU64::new(0),
-                listeners: RwLock::new(vec![]),
-            }),
+            inner: Arc::new(Inner::new()),
+            translator,
         }
     }
 
@@ -55,40 +203,121 @@ impl Broker {
 
     pub fn start(&self, interval: Duration) -> impl Future<Item = (), Error = ()> {
         let inner = self.inner.clone();
-        let logger = inner.logger.clone();
-
-        Interval::new_interval(interval)
-            .map_err(Into::into)
-            .for_each(move |_| {
-                // Get latest block and notify all listeners of the difference.
-                let inner = inner.clone();
-                inner.translator.get_latest_block().map(move |blk| {
-                    let last_notified_block = inner.last_notified_block.load(Ordering::SeqCst);
-                    let listeners = inner.listeners.read().unwrap();
-
-                    let to = blk.number_u64();
-
-                    // If there are no new blocks, return early.
-                    if to <= last_notified_block {
-                        return;
+        PUBSUB_HEALTHY.set(1);
+
+        // Forward pending transactions as soon as they are submitted.
+        let pending_inner = inner.clone();
+        let pending = self
+            .translator
+            .pending_pool()
+            .subscribe()
+            .for_each(move |hash| {
+                let listeners = pending_inner.listeners.read().unwrap();
+                for listener in listeners.iter() {
+                    if let Some(listener) = listener.upgrade() {
+                        listener.notify_pending_transaction(hash);
                     }
+                }
+                Ok(())
+            });
 
-                    let from = last_notified_block + 1;
+        // Follow new blocks through the node's block watch.
+        let blocks = follow_blocks(inner, self.translator.clone(), interval);
 
-                    for listener in listeners.iter() {
-                        if let Some(listener) = listener.upgrade() {
-                            listener.notify_blocks(from, to);
-                        }
-                    }
+        blocks.join(pending).map(|_| ())
+    }
+}
 
-                    inner.last_notified_block.store(to, Ordering::SeqCst);
-                })
-            })
-            .map_err(move |err| {
-                error!(logger, "Pub/sub notifier error";
-                    "err" => ?err,
-                );
-                abort();
-            })
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{mpsc, Mutex},
+    };
+
+    use anyhow::anyhow;
+    use futures::{future, stream, Future, Stream};
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    /// Block source which replays scripted block watches and polls.
+    struct StubSource {
+        watches: Mutex<VecDeque<Vec<Result<u64, Error>>>>,
+        latest: Mutex<VecDeque<Result<u64, Error>>>,
+    }
+
+    impl BlockSource for StubSource {
+        fn watch(&self) -> Box<dyn Stream<Item = u64, Error = Error> + Send> {
+            match self.watches.lock().unwrap().pop_front() {
+                Some(blocks) => Box::new(stream::iter_result(blocks)),
+                // Once the script is over, the watch stays open.
+                None => Box::new(future::empty().into_stream()),
+            }
+        }
+
+        fn latest(&self) -> Box<dyn Future<Item = u64, Error = Error> + Send> {
+            match self.latest.lock().unwrap().pop_front() {
+                Some(result) => Box::new(future::result(result)),
+                None => Box::new(future::err(anyhow!("no latest block"))),
+            }
+        }
+    }
+
+    /// Listener which forwards the notified block ranges.
+    struct StubListener {
+        blocks: Mutex<mpsc::Sender<(u64, u64)>>,
+    }
+
+    impl Listener for StubListener {
+        fn notify_blocks(&self, from_block: u64, to_block: u64) {
+            let _ = self.blocks.lock().unwrap().send((from_block, to_block));
+        }
+
+        fn notify_completed_transaction(&self, _entry: &TxEntry, _output: Vec<u8>) {}
+
+        fn notify_pending_transaction(&self, _hash: H256) {}
+    }
+
+    #[test]
+    fn test_follow_blocks_recovers() {
+        // The watch fails twice and so does polling, before block 5 arrives.
+        let source = Arc::new(StubSource {
+            watches: Mutex::new(
+                vec![
+                    vec![Err(anyhow!("watch failed"))],
+                    vec![Err(anyhow!("watch failed"))],
+                    vec![Ok(5)],
+                ]
+                .into(),
+            ),
+            latest: Mutex::new(
+                vec![Err(anyhow!("poll failed")), Err(anyhow!("poll failed"))].into(),
+            ),
+        });
+        let (sender, receiver) = mpsc::channel();
+        let listener = Arc::new(StubListener {
+            blocks: Mutex::new(sender),
+        });
+        let inner = Arc::new(Inner::new());
+        let weak: Weak<dyn Listener> = Arc::downgrade(&listener);
+        inner.listeners.write().unwrap().push(weak);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(follow_blocks(
+            inner.clone(),
+            source,
+            Duration::from_millis(1),
+        ));
+
+        // All blocks up to the first one after the outage are notified at once.
+        let range = receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("blocks should be notified");
+        assert_eq!(range, (1, 5));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        assert!(inner.healthy.load(Ordering::SeqCst));
+
+        runtime.shutdown_now().wait().unwrap();
     }
 }
@@ -0,0 +1,258 @@
+//! Retry policy for transaction submission.
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::Error;
+use grpcio::{self, RpcStatusCode};
+use lazy_static::lazy_static;
+use oasis_ethwasi_runtime_api::TransactionError;
+use prometheus::{labels, register_int_counter_vec, IntCounterVec};
+use rand::{self, Rng};
+
+// Metrics.
+lazy_static! {
+    static ref TX_SUBMISSIONS: IntCounterVec = register_int_counter_vec!(
+        "web3_gateway_tx_submissions",
+        "Number of transaction submission attempts by outcome",
+        &["outcome", "reason"]
+    )
+    .unwrap();
+}
+
+/// Reason for resubmitting a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryReason {
+    /// The transaction did not fit into the gas limit of the current batch.
+    BlockGasLimit,
+    /// The transaction did not fit into the payload limit of the current batch.
+    BatchPayloadLimit,
+    /// The node could not be reached or did not have the resources to accept
+    /// the request.
+    ///
+    /// Not retried by default, since the node may have accepted the
+    /// transaction after all.
+    Transient,
+}
+
+impl RetryReason {
+    /// Names of all reasons, as accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] =
+        &["block-gas-limit", "batch-payload-limit", "transient"];
+
+    /// Determines whether a submission error may go away on resubmission.
+    pub fn classify(err: &Error) -> Option<Self> {
+        if let Some(err) = err.downcast_ref::<TransactionError>() {
+            return match err {
+                TransactionError::BlockGasLimitReached => Some(RetryReason::BlockGasLimit),
+                TransactionError::BatchPayloadLimitReached => Some(RetryReason::BatchPayloadLimit),
+                _ => None,
+            };
+        }
+
+        match err.downcast_ref::<grpcio::Error>() {
+            // Errors such as an exceeded deadline leave it open whether the
+            // transaction was accepted, so resubmitting could send it twice.
+            Some(&grpcio::Error::RpcFailure(ref status)) => match status.status {
+                RpcStatusCode::Unavailable | RpcStatusCode::ResourceExhausted => {
+                    Some(RetryReason::Transient)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            RetryReason::BlockGasLimit => Self::NAMES[0],
+            RetryReason::BatchPayloadLimit => Self::NAMES[1],
+            RetryReason::Transient => Self::NAMES[2],
+        }
+    }
+}
+
+impl fmt::Display for RetryReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RetryReason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block-gas-limit" => Ok(RetryReason::BlockGasLimit),
+            "batch-payload-limit" => Ok(RetryReason::BatchPayloadLimit),
+            "transient" => Ok(RetryReason::Transient),
+            _ => Err(format!("unknown retry reason: {}", s)),
+        }
+    }
+}
+
+/// Policy for resubmitting transactions whose submission failed.
+///
+/// Resubmissions are delayed with exponential backoff. Half of each delay is
+/// random, so that clients which failed together do not retry together.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Errors on which to resubmit.
+    pub reasons: Vec<RetryReason>,
+    /// Maximum number of resubmissions.
+    pub max_retries: usize,
+    /// Delay before the first resubmission.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between resubmissions.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            reasons: vec![RetryReason::BlockGasLimit, RetryReason::BatchPayloadLimit],
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the reason for resubmitting after `err`, if the policy allows
+    /// retrying such errors.
+    pub fn should_retry(&self, err: &Error) -> Option<RetryReason> {
+        RetryReason::classify(err).filter(|reason| self.reasons.contains(reason))
+    }
+
+    /// Delay before resubmission number `retry` (starting at zero).
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .checked_mul(1 << retry.min(16))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+
+        let half = backoff / 2;
+        let jitter = rand::thread_rng().gen_range(0, half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter)
+    }
+}
+
+/// Counts a submission which yielded an execution result.
+pub fn record_executed() {
+    TX_SUBMISSIONS
+        .with(&labels! {"outcome" => "executed", "reason" => "none",})
+        .inc();
+}
+
+/// Counts a submission which failed with an error that is not retried.
+pub fn record_failed() {
+    TX_SUBMISSIONS
+        .with(&labels! {"outcome" => "failed", "reason" => "none",})
+        .inc();
+}
+
+/// Counts a submission which failed and will be retried.
+pub fn record_retried(reason: RetryReason) {
+    TX_SUBMISSIONS
+        .with(&labels! {"outcome" => "retried", "reason" => reason.name(),})
+        .inc();
+}
+
+/// Counts a submission which failed after all retries were used up.
+pub fn record_exhausted(reason: RetryReason) {
+    TX_SUBMISSIONS
+        .with(&labels! {"outcome" => "exhausted", "reason" => reason.name(),})
+        .inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use grpcio::RpcStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let err = Error::from(TransactionError::BlockGasLimitReached);
+        assert_eq!(
+            RetryReason::classify(&err),
+            Some(RetryReason::BlockGasLimit)
+        );
+        let err = Error::from(TransactionError::BatchPayloadLimitReached);
+        assert_eq!(
+            RetryReason::classify(&err),
+            Some(RetryReason::BatchPayloadLimit)
+        );
+        let err = Error::from(TransactionError::GasPrice);
+        assert_eq!(RetryReason::classify(&err), None);
+
+        let err = Error::from(grpcio::Error::RpcFailure(RpcStatus::new(
+            RpcStatusCode::Unavailable,
+            None,
+        )));
+        assert_eq!(RetryReason::classify(&err), Some(RetryReason::Transient));
+        let err = Error::from(grpcio::Error::RpcFailure(RpcStatus::new(
+            RpcStatusCode::ResourceExhausted,
+            None,
+        )));
+        assert_eq!(RetryReason::classify(&err), Some(RetryReason::Transient));
+        for status in &[
+            RpcStatusCode::InvalidArgument,
+            RpcStatusCode::DeadlineExceeded,
+            RpcStatusCode::Aborted,
+        ] {
+            let err = Error::from(grpcio::Error::RpcFailure(RpcStatus::new(*status, None)));
+            assert_eq!(RetryReason::classify(&err), None);
+        }
+
+        assert_eq!(RetryReason::classify(&anyhow!("other")), None);
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy {
+            reasons: vec![RetryReason::Transient],
+            ..Default::default()
+        };
+        let err = Error::from(TransactionError::BlockGasLimitReached);
+        assert_eq!(policy.should_retry(&err), None);
+        let err = Error::from(grpcio::Error::RpcFailure(RpcStatus::new(
+            RpcStatusCode::Unavailable,
+            None,
+        )));
+        assert_eq!(policy.should_retry(&err), Some(RetryReason::Transient));
+
+        // Transient errors are only retried on request.
+        assert_eq!(RetryPolicy::default().should_retry(&err), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let backoff = policy.backoff(0);
+            assert!(backoff >= Duration::from_millis(50) && backoff <= Duration::from_millis(100));
+            let backoff = policy.backoff(2);
+            assert!(backoff >= Duration::from_millis(200) && backoff <= Duration::from_millis(400));
+            // Capped by the maximum backoff.
+            let backoff = policy.backoff(40);
+            assert!(
+                backoff >= Duration::from_millis(500) && backoff <= Duration::from_millis(1000)
+            );
+        }
+    }
+
+    #[test]
+    fn test_reason_names() {
+        for name in RetryReason::NAMES {
+            let reason: RetryReason = name.parse().unwrap();
+            assert_eq!(reason.to_string(), *name);
+        }
+        assert!("unknown".parse::<RetryReason>().is_err());
+    }
+}
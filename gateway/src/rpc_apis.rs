@@ -161,7 +161,7 @@ impl FullDependencies {
         S: core::Middleware<Metadata>,
     {
         use parity_rpc::v1::{Eth, EthFilter, EthPubSub, EthSigning, Net, Web3};
-        use traits::Oasis;
+        use traits::{EthAccessList, EthFees, EthProof, Oasis};
 
         for api in apis {
             match *api {
@@ -173,7 +173,23 @@ impl FullDependencies {
                 }
                 Api::Eth => {
                     let client = EthClient::new(self.translator.clone());
-                    handler.extend_with(client.to_delegate());
+                    handler.extend_with(Eth::to_delegate(client));
+
+                    // Replaces `eth_call` from the `Eth` delegate, which does
+                    // not accept state overrides.
+                    let call_client = EthClient::new(self.translator.clone());
+                    handler.add_method_with_meta("eth_call", move |params, meta| {
+                        call_client.call_with_params(meta, params)
+                    });
+
+                    let proof_client = EthClient::new(self.translator.clone());
+                    handler.extend_with(EthProof::to_delegate(proof_client));
+
+                    let access_list_client = EthClient::new(self.translator.clone());
+                    handler.extend_with(EthAccessList::to_delegate(access_list_client));
+
+                    let fees_client = EthClient::new(self.translator.clone());
+                    handler.extend_with(EthFees::to_delegate(fees_client));
 
                     let signing_client = EthSigningClient::new();
                     handler.extend_with(signing_client.to_delegate());
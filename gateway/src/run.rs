@@ -23,16 +23,22 @@ use std::{
 use anyhow::{Error, Result};
 use ethereum_types::U256;
 use informant;
+use oasis_core_client::transaction::api::storage::StorageClient;
 use oasis_core_keymanager_client::KeyManagerClient;
 use oasis_core_runtime::common::logger::get_logger;
+use oasis_ethwasi_runtime_common::SizeLimits;
 use rpc::{self, HttpConfiguration, WsConfiguration};
 use rpc_apis;
 use slog::{info, warn, Logger};
 
-use crate::{pubsub::Broker, translator::Translator, EthereumRuntimeClient};
+use crate::{
+    cache::CacheConfig, pubsub::Broker, retry::RetryPolicy, translator::Translator,
+    EthereumRuntimeClient,
+};
 
 pub fn execute(
     client: EthereumRuntimeClient,
+    storage_client: StorageClient,
     km_client: Arc<dyn KeyManagerClient>,
     pubsub_interval_secs: u64,
     interface: &str,
@@ -42,6 +48,10 @@ pub fn execute(
     ws_max_connections: usize,
     ws_rate_limit: usize,
     gas_price: U256,
+    size_limits: SizeLimits,
+    async_submission: bool,
+    retry_policy: RetryPolicy,
+    cache_config: CacheConfig,
     jsonrpc_max_batch_size: usize,
 ) -> Result<RunningGateway> {
     let logger = get_logger("gateway/execute");
@@ -56,7 +66,15 @@ pub fn execute(
         "Oasis Core node is fully synced, proceeding with initialization"
     );
 
-    let translator = Arc::new(Translator::new(client, gas_price));
+    let translator = Arc::new(Translator::new(
+        client,
+        storage_client,
+        gas_price,
+        size_limits,
+        async_submission,
+        retry_policy,
+        cache_config,
+    ));
     let broker = Arc::new(Broker::new(translator.clone()));
     runtime.spawn(broker.start(Duration::new(pubsub_interval_secs, 0)));
 
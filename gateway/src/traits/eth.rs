@@ -0,0 +1,143 @@
+//! Eth RPC extensions.
+use std::collections::HashMap;
+
+use jsonrpc_core::{BoxFuture, Result};
+use jsonrpc_macros::Trailing;
+
+use parity_rpc::v1::types::{BlockNumber, Bytes, CallRequest, H160, H256, U256};
+
+build_rpc_trait! {
+    pub trait EthProof {
+        type Metadata;
+        /// Returns the state of an account and the given storage slots
+        /// together with MKVS inclusion proofs against the block's state root.
+        #[rpc(name = "eth_getProof")]
+        fn proof(&self, H160, Vec<H256>, Trailing<BlockNumber>) -> BoxFuture<RpcAccountProof>;
+    }
+}
+
+build_rpc_trait! {
+    pub trait EthFees {
+        type Metadata;
+        /// Returns the base fees, gas usage and priority fees of a range of
+        /// blocks ending with the given block.
+        #[rpc(name = "eth_feeHistory")]
+        fn fee_history(&self, U256, BlockNumber, Trailing<Vec<f64>>) -> BoxFuture<RpcFeeHistory>;
+
+        /// Returns the priority fee per gas to pay on top of the base fee.
+        #[rpc(name = "eth_maxPriorityFeePerGas")]
+        fn max_priority_fee_per_gas(&self) -> Result<U256>;
+    }
+}
+
+build_rpc_trait! {
+    pub trait EthAccessList {
+        type Metadata;
+        /// Simulates a call and returns the accounts and storage slots it
+        /// touches, together with the gas it used.
+        #[rpc(meta, name = "eth_createAccessList")]
+        fn create_access_list(&self, Self::Metadata, CallRequest, Trailing<BlockNumber>)
+            -> BoxFuture<RpcAccessListResult>;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcAccountProof {
+    /// Account address.
+    pub address: H160,
+    /// State root the proofs were generated for.
+    #[serde(rename = "stateRoot")]
+    pub state_root: H256,
+    /// Account balance.
+    pub balance: U256,
+    /// Account nonce.
+    pub nonce: U256,
+    /// Proof of the account metadata.
+    #[serde(rename = "accountProof")]
+    pub account_proof: RpcKeyProof,
+    /// Proofs of the requested storage slots.
+    #[serde(rename = "storageProof")]
+    pub storage_proof: Vec<RpcStorageProof>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcStorageProof {
+    /// Storage slot.
+    pub key: H256,
+    /// Value of the storage slot.
+    pub value: H256,
+    /// Proof of the raw value, or null if the account does not exist.
+    pub proof: Option<RpcKeyProof>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcKeyProof {
+    /// MKVS key.
+    pub key: Bytes,
+    /// Raw value stored under the key, or null if there is none.
+    pub value: Option<Bytes>,
+    /// Root hash the proof was generated for.
+    #[serde(rename = "untrustedRoot")]
+    pub untrusted_root: H256,
+    /// Serialized proof entries.
+    pub entries: Vec<Option<Bytes>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcAccessListItem {
+    /// Account address.
+    pub address: H160,
+    /// Storage slots of the account.
+    #[serde(rename = "storageKeys")]
+    pub storage_keys: Vec<H256>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcAccessListResult {
+    /// Accounts and storage slots touched by the call.
+    #[serde(rename = "accessList")]
+    pub access_list: Vec<RpcAccessListItem>,
+    /// Gas used by the call.
+    #[serde(rename = "gasUsed")]
+    pub gas_used: U256,
+    /// Reason why the call failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcFeeHistory {
+    /// Number of the first block in the history.
+    #[serde(rename = "oldestBlock")]
+    pub oldest_block: U256,
+    /// Base fee of each block, including the block after the newest.
+    #[serde(rename = "baseFeePerGas")]
+    pub base_fee_per_gas: Vec<U256>,
+    /// Fraction of the block gas limit used by each block.
+    #[serde(rename = "gasUsedRatio")]
+    pub gas_used_ratio: Vec<f64>,
+    /// Rewards at the requested percentiles of the gas used in each block.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reward: Option<Vec<Vec<U256>>>,
+}
+
+/// Changes to an account which only apply to a single `eth_call`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpcAccountOverride {
+    /// Replacement balance.
+    pub balance: Option<U256>,
+    /// Replacement nonce. Unless `state` is given as well, the nonce can only
+    /// be raised, by up to 65536.
+    pub nonce: Option<U256>,
+    /// Replacement code.
+    pub code: Option<Bytes>,
+    /// Replacement storage. Slots which are not listed are cleared.
+    pub state: Option<HashMap<H256, H256>>,
+    /// Storage slots to change, keeping all others.
+    #[serde(rename = "stateDiff")]
+    pub state_diff: Option<HashMap<H256, H256>>,
+}
+
+/// Account changes to apply before an `eth_call`, by address.
+pub type RpcStateOverride = HashMap<H160, RpcAccountOverride>;
@@ -1,5 +1,9 @@
 //! RPC traits for the client.
 
+pub mod eth;
 pub mod oasis;
 
-pub use self::oasis::Oasis;
+pub use self::{
+    eth::{EthAccessList, EthFees, EthProof},
+    oasis::Oasis,
+};
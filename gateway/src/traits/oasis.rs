@@ -1,6 +1,6 @@
 //! Oasis RPC interface.
 use ethereum_types::Address;
-use jsonrpc_core::BoxFuture;
+use jsonrpc_core::{BoxFuture, Result};
 use jsonrpc_macros::Trailing;
 
 use parity_rpc::v1::types::{BlockNumber, Bytes, H160, H256, U64};
@@ -21,6 +21,17 @@ build_rpc_trait! {
         /// status code and return value.
         #[rpc(name = "oasis_invoke")]
         fn invoke(&self, Bytes) -> BoxFuture<RpcExecutionPayload>;
+
+        /// Returns information about the code of a contract, or null if
+        /// there is no code at the given address.
+        #[rpc(name = "oasis_getContractInfo")]
+        fn get_contract_info(&self, H160, Trailing<BlockNumber>)
+            -> BoxFuture<Option<RpcContractInfo>>;
+
+        /// Returns the submission status of a transaction sent through this
+        /// gateway, or null if the gateway does not know the transaction.
+        #[rpc(name = "oasis_getTransactionStatus")]
+        fn get_transaction_status(&self, H256) -> Result<Option<RpcTransactionStatus>>;
     }
 }
 
@@ -46,3 +57,82 @@ pub struct RpcPublicKeyPayload {
     /// i.e., Sign(ssk, (pk, t).
     pub signature: Bytes,
 }
+
+/// Kind of contract code.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RpcCodeKind {
+    /// EVM bytecode.
+    Evm,
+    /// ewasm module.
+    Wasm,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcContractInfo {
+    /// Kind of the contract code (without the Oasis header).
+    pub kind: RpcCodeKind,
+    /// Hash of the stored code (including the Oasis header).
+    #[serde(rename = "codeHash")]
+    pub code_hash: H256,
+    /// Size of the stored code (including the Oasis header).
+    #[serde(rename = "codeSize")]
+    pub code_size: U64,
+    /// Oasis header the contract was deployed with, if any.
+    pub header: Option<RpcOasisHeader>,
+    /// Entities imported by an ewasm module.
+    pub imports: Option<Vec<RpcWasmImport>>,
+    /// Entities exported by an ewasm module.
+    pub exports: Option<Vec<RpcWasmExport>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcOasisHeader {
+    /// Header version.
+    pub version: u16,
+    /// Whether the contract is confidential.
+    pub confidential: bool,
+    /// Expiration timestamp of the contract (seconds since the epoch).
+    pub expiry: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcWasmImport {
+    /// Namespace of the import.
+    pub module: String,
+    /// Name of the import within its namespace.
+    pub name: String,
+    /// Kind of the import (function, table, memory or global).
+    pub kind: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcWasmExport {
+    /// Name of the export.
+    pub name: String,
+    /// Kind of the export (function, table, memory or global).
+    pub kind: String,
+}
+
+/// Submission state of a transaction.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RpcSubmissionState {
+    /// The transaction is being submitted.
+    Pending,
+    /// The transaction was executed and its receipt is available.
+    Executed,
+    /// The transaction could not be executed.
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcTransactionStatus {
+    /// Submission state.
+    pub state: RpcSubmissionState,
+    /// Number of times the transaction was resubmitted because it did not
+    /// fit into a batch.
+    pub retries: U64,
+    /// Reason why the transaction could not be executed.
+    pub error: Option<String>,
+}
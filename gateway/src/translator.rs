@@ -1,26 +1,35 @@
 //! Translator which translates between an Oasis Core chain running the Eth/WASI
 //! runtime and an Ethereum chain exposed to clients.
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    mem,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use anyhow::{anyhow, ensure, Error, Result};
 use ethcore::{
-    error::CallError,
+    error::{CallError, ExecutionError},
     executive::{contract_address, Executed, Executive, TransactOptions},
     filter::Filter,
     log_entry::{LocalizedLogEntry, LogEntry},
     receipt::{LocalizedReceipt, TransactionOutcome},
-    state::State,
-    transaction::{Action, LocalizedTransaction, SignedTransaction, UnverifiedTransaction},
+    state::{CleanupMode, State, MKVS_KEY_CODE, MKVS_KEY_METADATA},
+    transaction::{
+        Action, LocalizedTransaction, SignedTransaction, Transaction, UnverifiedTransaction,
+    },
     types::ids::BlockId,
-    vm::EnvInfo,
+    vm::{EnvInfo, Error as VmError},
 };
-use ethereum_types::{H256, H64, U256};
-use futures::{future, prelude::*};
+use ethereum_types::{Address, H256, H64, U256};
+use futures::{future, prelude::*, stream, sync::oneshot};
+use grpcio::CallOption;
 use hash::KECCAK_EMPTY_LIST_RLP;
 use io_context::Context;
 use lazy_static::lazy_static;
 use oasis_core_client::{
     transaction::{
+        api::storage::StorageClient,
         snapshot::{BlockSnapshot, TransactionSnapshot},
         Query, QueryCondition, ROUND_LATEST,
     },
@@ -28,12 +37,19 @@ use oasis_core_client::{
 };
 use oasis_core_runtime::{
     common::{cbor, crypto::hash::Hash, logger::get_logger},
-    storage::MKVS,
+    storage::{
+        mkvs::{
+            sync::{GetRequest, Proof, TreeID},
+            Root, RootType,
+        },
+        MKVS,
+    },
     transaction::types::{TxnCall, TxnOutput},
 };
-use oasis_ethwasi_runtime_api::{ExecutionResult, TransactionError, METHOD_TX};
+use oasis_ethwasi_runtime_api::{ExecutionResult, TransactionArgs, METHOD_TX};
 use oasis_ethwasi_runtime_common::{
-    genesis, parity::NullBackend, TAG_ETH_LOG_ADDRESS, TAG_ETH_LOG_TOPICS, TAG_ETH_TX_HASH,
+    check, genesis, parity::NullBackend, proof, SizeLimits, BLOCK_GAS_LIMIT, MIN_GAS_PRICE_GWEI,
+    TAG_ETH_LOG_ADDRESS, TAG_ETH_LOG_TOPICS, TAG_ETH_TX_HASH,
 };
 use parity_rpc::v1::types::{
     Block as EthRpcBlock, BlockTransactions as EthRpcBlockTransactions, Header as EthRpcHeader,
@@ -42,26 +58,60 @@ use parity_rpc::v1::types::{
 
 use serde_bytes::ByteBuf;
 use slog::{error, info, Logger};
+use tokio::{spawn, timer::Delay};
 use tokio_threadpool::{Builder as ThreadPoolBuilder, ThreadPool};
 
-use crate::EthereumRuntimeClient;
+use crate::{
+    cache::{CacheConfig, ChainCache},
+    pending::{Insertion, PendingPool, SubmissionStatus},
+    retry::{self, RetryPolicy},
+    util::reward_percentiles,
+    EthereumRuntimeClient,
+};
+
+/// Maximum number of blocks in a fee history.
+const MAX_FEE_HISTORY_BLOCKS: u64 = 1024;
+/// Maximum number of blocks in a block range fetch.
+pub const MAX_BLOCK_RANGE: u64 = 256;
+/// Maximum number of blocks fetched from the node at the same time.
+const BLOCK_FETCH_CONCURRENCY: usize = 16;
 
 /// Translator that enables exposing the Eth/WASI runtime on Oasis Core as an
 /// Ethereum chain.
 pub struct Translator {
     logger: Logger,
     client: Arc<EthereumRuntimeClient>,
+    storage_client: StorageClient,
     gas_price: U256,
+    size_limits: SizeLimits,
+    async_submission: bool,
+    retry_policy: RetryPolicy,
+    pending: Arc<PendingPool>,
+    cache: Arc<ChainCache>,
     simulator_pool: Arc<ThreadPool>,
 }
 
 impl Translator {
     /// Create new translator.
-    pub fn new(client: EthereumRuntimeClient, gas_price: U256) -> Self {
+    pub fn new(
+        client: EthereumRuntimeClient,
+        storage_client: StorageClient,
+        gas_price: U256,
+        size_limits: SizeLimits,
+        async_submission: bool,
+        retry_policy: RetryPolicy,
+        cache_config: CacheConfig,
+    ) -> Self {
         Self {
             logger: get_logger("gateway/translator"),
             client: Arc::new(client),
+            storage_client,
             gas_price,
+            size_limits,
+            async_submission,
+            retry_policy,
+            pending: Arc::new(PendingPool::new()),
+            cache: Arc::new(ChainCache::new(&cache_config)),
             simulator_pool: Arc::new(
                 ThreadPoolBuilder::new()
                     .name_prefix("simulator-pool-")
@@ -75,6 +125,25 @@ impl Translator {
         self.gas_price
     }
 
+    /// Base fee per gas.
+    ///
+    /// Gas is sold at a flat price, so this is the minimum gas price accepted
+    /// by the runtime.
+    pub fn base_fee(&self) -> U256 {
+        MIN_GAS_PRICE_GWEI.into()
+    }
+
+    /// Priority fee per gas to suggest to clients, such that the base fee and
+    /// the priority fee add up to the gas price.
+    pub fn max_priority_fee(&self) -> U256 {
+        self.gas_price.saturating_sub(self.base_fee())
+    }
+
+    /// Transactions submitted through this gateway which are not yet mined.
+    pub fn pending_pool(&self) -> &Arc<PendingPool> {
+        &self.pending
+    }
+
     /// Retrieve an Ethereum block given a block identifier.
     pub fn get_block(
         &self,
@@ -111,10 +180,27 @@ impl Translator {
     /// Retrieve the latest Ethereum block.
     pub fn get_latest_block(&self) -> impl Future<Item = EthereumBlock, Error = Error> {
         let client = self.client.clone();
+        let cache = self.cache.clone();
         self.client
             .txn_client()
             .get_latest_block()
-            .map(|snapshot| EthereumBlock::new(snapshot, client))
+            .map(move |snapshot| {
+                cache.insert_block(&snapshot);
+                EthereumBlock::new(snapshot, client, cache)
+            })
+    }
+
+    /// Watch new Ethereum blocks as they are finalized.
+    pub fn watch_blocks(&self) -> impl Stream<Item = EthereumBlock, Error = Error> {
+        let client = self.client.clone();
+        let cache = self.cache.clone();
+        self.client
+            .txn_client()
+            .watch_blocks()
+            .map(move |snapshot| {
+                cache.insert_block(&snapshot);
+                EthereumBlock::new(snapshot, client.clone(), cache.clone())
+            })
     }
 
     /// Retrieve a specific Ethereum block, identified by its round number.
@@ -122,11 +208,19 @@ impl Translator {
         &self,
         round: u64,
     ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
-        let client = self.client.clone();
-        self.client
-            .txn_client()
-            .get_block(round)
-            .map(|snapshot| snapshot.map(|snapshot| EthereumBlock::new(snapshot, client)))
+        fetch_block(self.client.clone(), self.cache.clone(), round)
+    }
+
+    /// Retrieve the Ethereum blocks with rounds `from` to `to` (inclusive),
+    /// in order.
+    ///
+    /// At most `MAX_BLOCK_RANGE` blocks can be fetched at once.
+    pub fn get_blocks_by_range(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> impl Future<Item = Vec<EthereumBlock>, Error = Error> {
+        fetch_blocks(self.client.clone(), self.cache.clone(), from, to)
     }
 
     /// Retrieve a specific Ethereum block, identified by its block hash.
@@ -135,21 +229,52 @@ impl Translator {
         hash: H256,
     ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
         let client = self.client.clone();
-        self.client
-            .txn_client()
-            .get_block_by_hash(Hash::from(hash.as_ref() as &[u8]))
-            .map(|snapshot| snapshot.map(|snapshot| EthereumBlock::new(snapshot, client)))
+        let cache = self.cache.clone();
+        let block: BoxFuture<Option<EthereumBlock>> = match self.cache.block_by_hash(&hash) {
+            Some(snapshot) => Box::new(future::ok(Some(EthereumBlock::new(
+                snapshot, client, cache,
+            )))),
+            None => Box::new(
+                self.client
+                    .txn_client()
+                    .get_block_by_hash(Hash::from(hash.as_ref() as &[u8]))
+                    .map(move |snapshot| {
+                        snapshot.map(|snapshot| {
+                            cache.insert_block(&snapshot);
+                            EthereumBlock::new(snapshot, client, cache)
+                        })
+                    }),
+            ),
+        };
+
+        block
     }
 
     /// Retrieve a specific Ethereum transaction, identified by its transaction hash.
     pub fn get_txn_by_hash(
         &self,
         hash: H256,
-    ) -> impl Future<Item = Option<EthereumTransaction>, Error = Error> {
-        self.client
-            .txn_client()
-            .query_tx(TAG_ETH_TX_HASH, hash)
-            .map(|txn| txn.map(EthereumTransaction::new))
+    ) -> impl Future<Item = Option<Arc<EthereumTransaction>>, Error = Error> {
+        let txn: BoxFuture<Option<Arc<EthereumTransaction>>> = match self.cache.transaction(&hash) {
+            Some(txn) => Box::new(future::ok(Some(txn))),
+            None => {
+                let cache = self.cache.clone();
+                Box::new(
+                    self.client
+                        .txn_client()
+                        .query_tx(TAG_ETH_TX_HASH, hash)
+                        .map(move |snapshot| {
+                            snapshot.map(|snapshot| {
+                                let txn = Arc::new(EthereumTransaction::new(snapshot));
+                                cache.insert_transaction(hash, txn.clone());
+                                txn
+                            })
+                        }),
+                )
+            }
+        };
+
+        txn
     }
 
     /// Retrieve a specific Ethereum transaction, identified by the block round and
@@ -158,11 +283,8 @@ impl Translator {
         &self,
         round: u64,
         index: u32,
-    ) -> impl Future<Item = Option<EthereumTransaction>, Error = Error> {
-        self.client
-            .txn_client()
-            .get_tx(round, index)
-            .map(|txn| txn.map(EthereumTransaction::new))
+    ) -> impl Future<Item = Option<Arc<EthereumTransaction>>, Error = Error> {
+        fetch_txn(&self.client, self.cache.clone(), round, index)
     }
 
     /// Retrieve a specific Ethereum transaction, identified by the block hash and
@@ -171,11 +293,22 @@ impl Translator {
         &self,
         block_hash: H256,
         index: u32,
-    ) -> impl Future<Item = Option<EthereumTransaction>, Error = Error> {
-        self.client
-            .txn_client()
-            .get_tx_by_block_hash(Hash::from(block_hash.as_ref() as &[u8]), index)
-            .map(|txn| txn.map(EthereumTransaction::new))
+    ) -> impl Future<Item = Option<Arc<EthereumTransaction>>, Error = Error> {
+        let cache = self.cache.clone();
+        let txn: BoxFuture<Option<Arc<EthereumTransaction>>> = match self
+            .cache
+            .block_by_hash(&block_hash)
+        {
+            Some(snapshot) => fetch_txn(&self.client, cache, snapshot.block.header.round, index),
+            None => Box::new(
+                self.client
+                    .txn_client()
+                    .get_tx_by_block_hash(Hash::from(block_hash.as_ref() as &[u8]), index)
+                    .map(move |snapshot| snapshot.map(|snapshot| cache_txn(&cache, snapshot))),
+            ),
+        };
+
+        txn
     }
 
     /// Retrieve a specific Ethereum transaction, identified by a block identifier
@@ -184,8 +317,8 @@ impl Translator {
         &self,
         id: BlockId,
         index: u32,
-    ) -> impl Future<Item = Option<EthereumTransaction>, Error = Error> {
-        let txn: BoxFuture<Option<EthereumTransaction>> = match id {
+    ) -> impl Future<Item = Option<Arc<EthereumTransaction>>, Error = Error> {
+        let txn: BoxFuture<Option<Arc<EthereumTransaction>>> = match id {
             BlockId::Hash(hash) => Box::new(self.get_txn_by_block_hash_and_index(hash, index)),
             BlockId::Number(round) => Box::new(self.get_txn_by_round_and_index(round, index)),
             BlockId::Latest => Box::new(self.get_txn_by_round_and_index(ROUND_LATEST, index)),
@@ -195,70 +328,104 @@ impl Translator {
         txn
     }
 
-    /// Submit a raw Ethereum transaction to the chain.
+    /// Submit a raw Ethereum transaction to the chain and wait for its result.
+    ///
+    /// If the transaction is already being submitted, this waits for the
+    /// result of that submission instead of submitting it again.
     pub fn send_raw_transaction(&self, raw: Vec<u8>) -> BoxFuture<(H256, ExecutionResult)> {
-        // Try to decode the transaction.
-        let decoded: UnverifiedTransaction = match rlp::decode(&raw) {
-            Ok(decoded) => decoded,
-            Err(err) => return Box::new(future::err(err.into())),
-        };
+        let submission = self.submission();
+
+        Box::new(
+            self.verify_raw_transaction(&raw)
+                .and_then(move |signed| submission.send(raw, signed)),
+        )
+    }
+
+    /// Submit a raw Ethereum transaction to the chain without waiting for its
+    /// result.
+    ///
+    /// Resolves to the transaction hash once the transaction passes local
+    /// validation. The submission then proceeds in the background and its
+    /// status can be queried with `get_submission_status`.
+    pub fn submit_raw_transaction(&self, raw: Vec<u8>) -> BoxFuture<H256> {
+        let submission = self.submission();
+
+        Box::new(
+            self.verify_raw_transaction(&raw)
+                .map(move |signed| submission.spawn(raw, signed)),
+        )
+    }
+
+    /// Whether transactions should be submitted without waiting for their
+    /// results.
+    pub fn async_submission(&self) -> bool {
+        self.async_submission
+    }
+
+    /// Retrieve the status of a transaction submitted through this gateway.
+    pub fn get_submission_status(&self, hash: &H256) -> Option<SubmissionStatus> {
+        self.pending.status(hash)
+    }
 
-        // Try to verify the signature.
-        let signed = match SignedTransaction::new(decoded) {
+    /// Check a raw Ethereum transaction against the rules enforced by the
+    /// runtime and against the latest state of its sender, so that invalid
+    /// transactions are rejected without a round trip. The size limits are the
+    /// ones configured for the gateway, which should match the ones the
+    /// runtime was built with.
+    fn verify_raw_transaction(&self, raw: &[u8]) -> BoxFuture<SignedTransaction> {
+        let signed = match check::tx(raw, &self.size_limits) {
             Ok(signed) => signed,
-            Err(err) => return Box::new(future::err(err.into())),
+            Err(err) => return Box::new(future::err(err)),
         };
 
-        // If we get a BlockGasLimitReached error, retry up to 5 times.
-        const MAX_RETRIES: usize = 5;
+        Box::new(self.get_latest_block().and_then(move |blk| {
+            let state = blk.state()?;
+            let sender = signed.sender();
+            let nonce = state.nonce(&sender)?;
+            let balance = state.balance(&sender)?;
+            check::account(&signed, nonce, balance)?;
 
-        Box::new(future::loop_fn(
-            (
-                MAX_RETRIES,
-                self.client.clone(),
-                ByteBuf::from(raw),
-                signed,
-                self.logger.clone(),
-            ),
-            move |(retries, client, payload, signed, logger)| {
-                client
-                    .tx(payload.clone())
-                    .then(move |maybe_result| match maybe_result {
-                        Ok(result) => {
-                            let hash = signed.hash();
-                            info!(logger, "send_raw_transaction OK";
-                                "hash" => ?hash,
-                                "transaction" => ?signed,
-                                "result" => ?result
-                            );
-                            Ok(future::Loop::Break((hash, result)))
-                        }
-                        Err(err) => {
-                            if let Some(txn_err) = err.downcast_ref::<TransactionError>() {
-                                if let TransactionError::BlockGasLimitReached = txn_err {
-                                    if retries == 0 {
-                                        return Err(err);
-                                    }
-                                    let retries = retries - 1;
-                                    return Ok(future::Loop::Continue((
-                                        retries, client, payload, signed, logger,
-                                    )));
-                                }
-                            }
-                            let hash = signed.hash();
-                            info!(logger, "send_raw_transaction ERR";
-                                "hash" => ?hash,
-                                "transaction" => ?signed,
-                                "err" => ?err,
-                            );
-                            Err(err)
-                        }
-                    })
-            },
-        ))
+            Ok(signed)
+        }))
+    }
+
+    /// Prepare a submission with the configured retry policy.
+    fn submission(&self) -> Submission {
+        let client = self.client.clone();
+        Submission {
+            tx: Arc::new(move |args| client.tx(args)),
+            pending: self.pending.clone(),
+            logger: self.logger.clone(),
+            retry_policy: self.retry_policy.clone(),
+            retries: 0,
+        }
+    }
+
+    /// Retrieve the state of an account and the given storage slots at a given
+    /// block, together with MKVS inclusion proofs against the block's state root.
+    ///
+    /// # Notes
+    ///
+    /// Storage of confidential contracts is not supported.
+    pub fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        id: BlockId,
+    ) -> impl Future<Item = AccountProof, Error = Error> {
+        let simulator_pool = self.simulator_pool.clone();
+        let storage_client = self.storage_client.clone();
+
+        self.get_block_unwrap(id).and_then(move |blk| {
+            // Proofs are fetched with blocking calls, so keep them off the I/O threads.
+            simulator_pool.spawn_handle(future::lazy(move || {
+                blk.account_proof(&storage_client, address, slots)
+            }))
+        })
     }
 
-    /// Simulate a transaction against a given block.
+    /// Simulate a transaction against a given block, with the given changes
+    /// applied to its state.
     ///
     /// The simulated transaction is executed in a dedicated thread pool to
     /// avoid blocking I/O processing.
@@ -270,61 +437,146 @@ impl Translator {
         &self,
         transaction: SignedTransaction,
         id: BlockId,
+        overrides: StateOverride,
     ) -> impl Future<Item = Executed, Error = CallError> {
         let simulator_pool = self.simulator_pool.clone();
 
-        self.get_block(id)
-            .map_err(|_| CallError::StateCorrupt)
-            .and_then(|blk| match blk {
-                Some(blk) => Ok(blk),
-                None => Err(CallError::StatePruned),
-            })
+        self.get_simulation_block(id).and_then(move |blk| {
+            // Execute simulation in a dedicated thread pool to avoid blocking
+            // I/O processing with simulations.
+            simulator_pool.spawn_handle(future::lazy(move || {
+                blk.simulator().simulate(&transaction, &overrides)
+            }))
+        })
+    }
+
+    /// Estimates gas against a given block.
+    ///
+    /// Searches for the smallest gas limit between the intrinsic gas of the
+    /// transaction and the gas limit given by the caller, or the block gas
+    /// limit, with which the transaction succeeds, simulating the transaction
+    /// at each step.
+    ///
+    /// # Notes
+    ///
+    /// Confidential contracts are not supported.
+    pub fn estimate_gas(
+        &self,
+        transaction: SignedTransaction,
+        gas_cap: Option<U256>,
+        id: BlockId,
+    ) -> impl Future<Item = U256, Error = EstimateGasError> {
+        let simulator_pool = self.simulator_pool.clone();
+
+        self.get_simulation_block(id)
+            .map_err(EstimateGasError::from)
             .and_then(move |blk| {
-                // Execute simulation in a dedicated thread pool to avoid blocking
-                // I/O processing with simulations.
                 simulator_pool.spawn_handle(future::lazy(move || {
-                    let mut state = blk.state().map_err(|_| CallError::StateCorrupt)?;
-                    let env_info = EnvInfo {
-                        number: blk.snapshot.block.header.round + 1,
-                        author: Default::default(),
-                        timestamp: blk.snapshot.block.header.timestamp,
-                        difficulty: Default::default(),
-                        // TODO: Get 256 last hashes.
-                        last_hashes: Arc::new(vec![blk
-                            .snapshot
-                            .block
-                            .header
-                            .previous_hash
-                            .as_ref()
-                            .into()]),
-                        gas_used: Default::default(),
-                        gas_limit: U256::max_value(),
-                    };
-                    let machine = genesis::SPEC.engine.machine();
-                    let options = TransactOptions::with_no_tracing()
-                        .dont_check_nonce()
-                        .save_output_from_contract();
-
-                    Ok(Executive::new(&mut state, &env_info, machine)
-                        .transact_virtual(&transaction, options)?)
+                    blk.simulator().estimate_gas(&transaction, gas_cap)
                 }))
             })
     }
 
-    /// Estimates gas against a given block.
-    ///
-    /// Uses `simulate_transaction` internally.
+    /// Simulate a transaction against a given block and collect the accounts
+    /// and storage slots it touches.
     ///
     /// # Notes
     ///
     /// Confidential contracts are not supported.
-    pub fn estimate_gas(
+    pub fn create_access_list(
         &self,
         transaction: SignedTransaction,
         id: BlockId,
-    ) -> impl Future<Item = U256, Error = CallError> {
-        self.simulate_transaction(transaction, id)
-            .map(|executed| executed.gas_used + executed.refunded)
+    ) -> impl Future<Item = AccessList, Error = CallError> {
+        let simulator_pool = self.simulator_pool.clone();
+
+        self.get_simulation_block(id).and_then(move |blk| {
+            simulator_pool.spawn_handle(future::lazy(move || {
+                blk.simulator().access_list(&transaction)
+            }))
+        })
+    }
+
+    /// Retrieve the block on top of which transactions are simulated.
+    fn get_simulation_block(
+        &self,
+        id: BlockId,
+    ) -> impl Future<Item = EthereumBlock, Error = CallError> {
+        self.get_block(id)
+            .map_err(|_| CallError::StateCorrupt)
+            .and_then(|blk| match blk {
+                Some(blk) => Ok(blk),
+                None => Err(CallError::StatePruned),
+            })
+    }
+
+    /// Gas usage and rewards of up to `block_count` blocks ending with the
+    /// given block.
+    ///
+    /// The reward of a transaction is its gas price above the base fee. The
+    /// rewards of each block are given at the `percentiles` of its gas used,
+    /// which must be sorted in ascending order.
+    pub fn fee_history(
+        &self,
+        block_count: u64,
+        newest: BlockId,
+        percentiles: Vec<f64>,
+    ) -> impl Future<Item = FeeHistory, Error = Error> {
+        let block_count = block_count.min(MAX_FEE_HISTORY_BLOCKS);
+        let base_fee = self.base_fee();
+        let client = self.client.clone();
+        let cache = self.cache.clone();
+
+        self.get_block_unwrap(newest).and_then(move |newest| {
+            let newest = newest.number_u64();
+            let oldest = (newest + 1).saturating_sub(block_count);
+
+            stream::iter_ok((oldest..=newest).step_by(MAX_BLOCK_RANGE as usize))
+                .and_then(move |from| {
+                    let to = newest.min(from + MAX_BLOCK_RANGE - 1);
+                    fetch_blocks(client.clone(), cache.clone(), from, to)
+                })
+                .map(stream::iter_ok)
+                .flatten()
+                .map(|blk| blk.gas_usage())
+                .buffered(BLOCK_FETCH_CONCURRENCY)
+                .collect()
+                .map(move |blocks| {
+                    // Includes the base fee of the block after the newest.
+                    let base_fees = if blocks.is_empty() {
+                        0
+                    } else {
+                        blocks.len() + 1
+                    };
+                    let mut history = FeeHistory {
+                        oldest_block: oldest,
+                        base_fee_per_gas: vec![base_fee; base_fees],
+                        gas_used_ratio: Vec::with_capacity(blocks.len()),
+                        reward: Vec::with_capacity(blocks.len()),
+                    };
+
+                    for txns in blocks {
+                        let gas_used = txns
+                            .iter()
+                            .fold(U256::zero(), |sum, &(gas_used, _)| sum + gas_used);
+                        history
+                            .gas_used_ratio
+                            .push(gas_used.low_u64() as f64 / BLOCK_GAS_LIMIT as f64);
+
+                        let rewards = txns
+                            .into_iter()
+                            .map(|(gas_used, gas_price)| {
+                                (gas_used, gas_price.saturating_sub(base_fee))
+                            })
+                            .collect();
+                        history
+                            .reward
+                            .push(reward_percentiles(rewards, &percentiles));
+                    }
+
+                    history
+                })
+        })
     }
 
     /// Looks up logs based on the given filter.
@@ -407,9 +659,9 @@ impl Translator {
                     return vec![];
                 }
 
-                // We know that arguments are raw Ethereum transaction bytes.
-                let raw: ByteBuf = match cbor::from_value(txn.input.args.clone()) {
-                    Ok(raw) => raw,
+                // We know that arguments contain raw Ethereum transaction bytes.
+                let args: TransactionArgs = match cbor::from_value(txn.input.args.clone()) {
+                    Ok(args) => args,
                     Err(err) => {
                         error!(logger, "Error while decoding ethereum transaction input";
                             "err" => ?err,
@@ -417,7 +669,7 @@ impl Translator {
                         return vec![];
                     }
                 };
-                let eth_tx: UnverifiedTransaction = match rlp::decode(&raw) {
+                let eth_tx: UnverifiedTransaction = match rlp::decode(args.raw()) {
                     Ok(tx) => tx,
                     Err(err) => {
                         error!(logger, "Error while decoding ethereum transaction input";
@@ -486,16 +738,310 @@ impl Translator {
     }
 }
 
+/// Gas usage and rewards of a range of blocks.
+#[derive(Debug)]
+pub struct FeeHistory {
+    /// Number of the first block in the range.
+    pub oldest_block: u64,
+    /// Base fee of each block, including the block after the range.
+    pub base_fee_per_gas: Vec<U256>,
+    /// Fraction of the block gas limit used by each block.
+    pub gas_used_ratio: Vec<f64>,
+    /// Rewards at the requested percentiles of each block.
+    pub reward: Vec<Vec<U256>>,
+}
+
+/// Gas estimation error.
+#[derive(Debug)]
+pub enum EstimateGasError {
+    /// The transaction could not be simulated.
+    Call(CallError),
+    /// The transaction fails even with the highest gas limit allowed.
+    Execution { exception: VmError, output: Vec<u8> },
+}
+
+impl From<CallError> for EstimateGasError {
+    fn from(err: CallError) -> Self {
+        EstimateGasError::Call(err)
+    }
+}
+
+/// Accounts and storage slots touched by a simulated transaction.
+#[derive(Debug)]
+pub struct AccessList {
+    /// Storage slots read or written, by account.
+    pub accounts: BTreeMap<Address, BTreeSet<H256>>,
+    /// Gas used by the transaction.
+    pub gas_used: U256,
+    /// Exception which made the transaction fail, if any.
+    pub exception: Option<VmError>,
+    /// Return value of the transaction.
+    pub output: Vec<u8>,
+}
+
+/// Maximum number of steps by which the nonce of an account can be raised
+/// without replacing its storage.
+const MAX_NONCE_OVERRIDE_STEPS: u64 = 1 << 16;
+
+/// Changes to an account which only apply to a single simulation.
+#[derive(Clone, Debug, Default)]
+pub struct AccountOverride {
+    /// Replacement balance.
+    pub balance: Option<U256>,
+    /// Replacement nonce. Unless the storage is replaced as well, the nonce
+    /// can only be raised, by up to `MAX_NONCE_OVERRIDE_STEPS`.
+    pub nonce: Option<U256>,
+    /// Replacement code.
+    pub code: Option<Vec<u8>>,
+    /// Replacement storage. Slots which are not listed are cleared.
+    pub storage: Option<HashMap<H256, H256>>,
+    /// Storage slots to change, keeping all others.
+    pub storage_diff: HashMap<H256, H256>,
+}
+
+/// Account changes to apply before a simulation, by address.
+pub type StateOverride = HashMap<Address, AccountOverride>;
+
+impl AccountOverride {
+    /// Apply the changes to an account in the given state.
+    fn apply(&self, state: &mut State<NullBackend>, address: &Address) -> Result<(), CallError> {
+        fn corrupt<E>(_: E) -> CallError {
+            CallError::StateCorrupt
+        }
+
+        let balance = state.balance(address).map_err(corrupt)?;
+        let nonce = state.nonce(address).map_err(corrupt)?;
+
+        if let Some(ref storage) = self.storage {
+            // Start over from an account without any storage.
+            let code = match self.code {
+                Some(ref code) => Some(code.clone()),
+                None => state
+                    .code(address)
+                    .map_err(corrupt)?
+                    .map(|code| (*code).clone()),
+            };
+            let expiry = state.storage_expiry(address).map_err(corrupt)?;
+            state.kill_account(address);
+            state.new_contract(
+                address,
+                self.balance.unwrap_or(balance),
+                self.nonce.unwrap_or(nonce),
+                expiry,
+            );
+            if let Some(code) = code {
+                state.init_code(address, code).map_err(corrupt)?;
+            }
+            for (key, value) in storage {
+                state.set_storage(address, *key, *value).map_err(corrupt)?;
+            }
+        } else {
+            match self.balance {
+                Some(new) if new > balance => state
+                    .add_balance(address, &(new - balance), CleanupMode::NoEmpty)
+                    .map_err(corrupt)?,
+                Some(new) if new < balance => state
+                    .sub_balance(address, &(balance - new), &mut CleanupMode::NoEmpty)
+                    .map_err(corrupt)?,
+                _ => {}
+            }
+
+            // The state only supports incrementing nonces in place.
+            if let Some(new) = self.nonce {
+                if new < nonce || new - nonce > MAX_NONCE_OVERRIDE_STEPS.into() {
+                    return Err(CallError::Execution(ExecutionError::Internal(format!(
+                        "nonce of {:?} must be between {} and {} unless its state is replaced",
+                        address,
+                        nonce,
+                        nonce + U256::from(MAX_NONCE_OVERRIDE_STEPS),
+                    ))));
+                }
+                for _ in 0..(new - nonce).low_u64() {
+                    state.inc_nonce(address).map_err(corrupt)?;
+                }
+            }
+
+            if let Some(ref code) = self.code {
+                state.reset_code(address, code.clone()).map_err(corrupt)?;
+            }
+        }
+
+        for (key, value) in &self.storage_diff {
+            state.set_storage(address, *key, *value).map_err(corrupt)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Calls the runtime's `tx` method.
+type TxFn = Arc<dyn Fn(TransactionArgs) -> BoxFuture<ExecutionResult> + Send + Sync>;
+
+/// Submission of transactions to the runtime, which are resubmitted according
+/// to a retry policy.
+struct Submission {
+    tx: TxFn,
+    pending: Arc<PendingPool>,
+    logger: Logger,
+    retry_policy: RetryPolicy,
+    retries: usize,
+}
+
+impl Submission {
+    /// Submit a verified transaction and wait for its result.
+    ///
+    /// If the transaction is already in flight, waits for the result of that
+    /// submission instead.
+    fn send(self, raw: Vec<u8>, signed: SignedTransaction) -> BoxFuture<(H256, ExecutionResult)> {
+        let hash = signed.hash();
+        match self.pending.insert(signed.clone()) {
+            Insertion::Added => self.run(raw, signed),
+            Insertion::InFlight(result) => Box::new(result.then(move |result| match result {
+                Ok(Ok(result)) => Ok((hash, result)),
+                Ok(Err(err)) => Err(anyhow!(err)),
+                Err(oneshot::Canceled) => Err(anyhow!("transaction submission abandoned")),
+            })),
+        }
+    }
+
+    /// Submit a verified transaction in the background, unless it is already
+    /// in flight, and return its hash.
+    fn spawn(self, raw: Vec<u8>, signed: SignedTransaction) -> H256 {
+        let hash = signed.hash();
+        if let Insertion::Added = self.pending.insert(signed.clone()) {
+            let logger = self.logger.clone();
+            spawn(self.run(raw, signed).then(move |result| {
+                if let Err(err) = result {
+                    error!(logger, "Asynchronous transaction submission failed";
+                        "hash" => ?hash,
+                        "err" => ?err,
+                    );
+                }
+                Ok(())
+            }));
+        }
+
+        hash
+    }
+
+    /// Submit a verified transaction, which must already be in the pending
+    /// pool, and remove it from the pool once its result is known.
+    fn run(self, raw: Vec<u8>, signed: SignedTransaction) -> BoxFuture<(H256, ExecutionResult)> {
+        let hash = signed.hash();
+        let pending = self.pending.clone();
+        let payload = TransactionArgs::Raw(ByteBuf::from(raw));
+
+        let result = future::loop_fn(self, move |submission| {
+            submission.attempt(payload.clone(), signed.clone())
+        });
+
+        Box::new(result.then(move |result| {
+            pending.finish(
+                &hash,
+                result
+                    .as_ref()
+                    .map(|(_, result)| result.clone())
+                    .map_err(|err| err.to_string()),
+            );
+            result
+        }))
+    }
+
+    fn attempt(
+        self,
+        payload: TransactionArgs,
+        signed: SignedTransaction,
+    ) -> BoxFuture<future::Loop<(H256, ExecutionResult), Submission>> {
+        let hash = signed.hash();
+        let response = (self.tx)(payload);
+
+        Box::new(
+            response.then(move |maybe_result| -> BoxFuture<future::Loop<_, _>> {
+                let err = match maybe_result {
+                    Ok(result) => {
+                        retry::record_executed();
+                        info!(self.logger, "send_raw_transaction OK";
+                            "hash" => ?hash,
+                            "transaction" => ?signed,
+                            "result" => ?result
+                        );
+                        return Box::new(future::ok(future::Loop::Break((hash, result))));
+                    }
+                    Err(err) => err,
+                };
+
+                match self.retry_policy.should_retry(&err) {
+                    Some(reason) if self.retries < self.retry_policy.max_retries => {
+                        retry::record_retried(reason);
+                        let backoff = self.retry_policy.backoff(self.retries);
+                        info!(self.logger, "send_raw_transaction RETRY";
+                            "hash" => ?hash,
+                            "reason" => %reason,
+                            "backoff" => ?backoff,
+                            "err" => ?err,
+                        );
+                        self.pending.retry(&hash);
+
+                        let mut submission = self;
+                        submission.retries += 1;
+                        Box::new(
+                            Delay::new(Instant::now() + backoff)
+                                .map_err(Error::from)
+                                .map(move |()| future::Loop::Continue(submission)),
+                        )
+                    }
+                    reason => {
+                        match reason {
+                            Some(reason) => retry::record_exhausted(reason),
+                            None => retry::record_failed(),
+                        }
+                        info!(self.logger, "send_raw_transaction ERR";
+                            "hash" => ?hash,
+                            "transaction" => ?signed,
+                            "err" => ?err,
+                        );
+                        Box::new(future::err(err))
+                    }
+                }
+            }),
+        )
+    }
+}
+
 /// A wrapper that exposes an Oasis Core transaction against the Eth/WASI runtime
 /// as an Ethereum transaction.
 pub struct EthereumTransaction {
     snapshot: TransactionSnapshot,
+    /// Decoded receipt, once it has been retrieved.
+    receipt: Mutex<Option<LocalizedReceipt>>,
 }
 
 impl EthereumTransaction {
     /// Create a new Ethereum transaction from an Oasis Core transaction snapshot.
     pub fn new(snapshot: TransactionSnapshot) -> Self {
-        Self { snapshot }
+        Self {
+            snapshot,
+            receipt: Mutex::new(None),
+        }
+    }
+
+    /// Round of the block which contains the transaction.
+    pub fn round(&self) -> u64 {
+        self.snapshot.block_snapshot.block.header.round
+    }
+
+    /// Index of the transaction within its block.
+    pub fn index(&self) -> u32 {
+        self.snapshot.index
+    }
+
+    /// Whether the transaction was executed, as opposed to being rejected by
+    /// the runtime.
+    pub fn is_executed(&self) -> bool {
+        match self.snapshot.output {
+            TxnOutput::Success(_) => true,
+            TxnOutput::Error(_) => false,
+        }
     }
 
     /// Retrieve the (localized) Ethereum transaction input.
@@ -506,9 +1052,9 @@ impl EthereumTransaction {
             "not an Ethereum transaction"
         );
 
-        // We know that arguments are raw Ethereum transaction bytes.
-        let raw: ByteBuf = cbor::from_value(self.snapshot.input.args.clone())?;
-        let signed: UnverifiedTransaction = rlp::decode(&raw)?;
+        // We know that arguments contain raw Ethereum transaction bytes.
+        let args: TransactionArgs = cbor::from_value(self.snapshot.input.args.clone())?;
+        let signed: UnverifiedTransaction = rlp::decode(args.raw())?;
 
         Ok(LocalizedTransaction {
             signed,
@@ -521,6 +1067,16 @@ impl EthereumTransaction {
 
     /// Retrieve the (localized) Ethereum transaction output (receipt).
     pub fn receipt(&self) -> Result<LocalizedReceipt> {
+        if let Some(ref receipt) = *self.receipt.lock().unwrap() {
+            return Ok(receipt.clone());
+        }
+
+        let receipt = self.decode_receipt()?;
+        *self.receipt.lock().unwrap() = Some(receipt.clone());
+        Ok(receipt)
+    }
+
+    fn decode_receipt(&self) -> Result<LocalizedReceipt> {
         match self.snapshot.output {
             TxnOutput::Success(ref value) => {
                 // We know that output is ExecutionResult.
@@ -579,38 +1135,53 @@ impl EthereumTransaction {
     }
 }
 
-/// A wrapper that exposes an Oasis Core block generated by the Eth/WASI runtime
-/// as an Ethereum block.
-pub struct EthereumBlock {
-    snapshot: BlockSnapshot,
-    client: Arc<EthereumRuntimeClient>,
+/// MKVS inclusion proof for a single key.
+pub struct KeyProof {
+    /// MKVS key.
+    pub key: Vec<u8>,
+    /// Value stored under the key, if any.
+    pub value: Option<Vec<u8>>,
+    /// Proof of the value against the block's state root.
+    pub proof: Proof,
 }
 
-impl EthereumBlock {
-    /// Create a new Ethereum block from an Oasis Core block snapshot.
-    pub fn new(snapshot: BlockSnapshot, client: Arc<EthereumRuntimeClient>) -> Self {
-        Self { snapshot, client }
-    }
-
-    /// Ethereum block number.
-    pub fn number(&self) -> U256 {
-        self.snapshot.block.header.round.into()
-    }
+/// Storage slot with an MKVS inclusion proof.
+pub struct StorageProof {
+    /// Storage slot.
+    pub slot: H256,
+    /// Value of the storage slot.
+    pub value: H256,
+    /// Proof of the raw value, if the slot is backed by storage.
+    pub proof: Option<KeyProof>,
+}
 
-    /// Ethereum block number as an u64.
-    pub fn number_u64(&self) -> u64 {
-        self.snapshot.block.header.round
-    }
+/// Account state with MKVS inclusion proofs.
+pub struct AccountProof {
+    /// State root the proofs were generated for.
+    pub state_root: H256,
+    /// Account balance.
+    pub balance: U256,
+    /// Account nonce.
+    pub nonce: U256,
+    /// Proof of the account metadata.
+    pub account: KeyProof,
+    /// Proofs of the requested storage slots.
+    pub storage: Vec<StorageProof>,
+}
 
-    /// Block hash.
-    pub fn hash(&self) -> H256 {
-        self.snapshot.block_hash.as_ref().into()
-    }
+/// Simulation of transactions on top of a state.
+struct Simulator {
+    /// State to simulate on, which is never modified.
+    mkvs: Box<dyn ethcore::mkvs::MKVS>,
+    /// Environment of the simulated transactions.
+    env_info: EnvInfo,
+}
 
-    /// Ethereum state snapshot at given block.
-    pub fn state(&self) -> Result<State<NullBackend>> {
+impl Simulator {
+    /// Ethereum state to simulate on.
+    fn state(&self) -> Result<State<NullBackend>> {
         Ok(State::from_existing(
-            Box::new(BlockSnapshotMKVS(self.snapshot.clone())),
+            self.mkvs.boxed_clone(),
             NullBackend,
             U256::zero(),       /* account_start_nonce */
             Default::default(), /* factories */
@@ -618,39 +1189,344 @@ impl EthereumBlock {
         )?)
     }
 
-    /// Raw Oasis Core transactions in a block corresponding to Ethereum transactions.
-    pub fn raw_transactions(
-        &self,
-    ) -> impl Future<Item = impl Iterator<Item = TxnCall>, Error = Error> {
+    /// Ethereum state to simulate on, which records the MKVS keys that are
+    /// read.
+    fn recording_state(&self, reads: Arc<Mutex<Vec<Vec<u8>>>>) -> Result<State<NullBackend>> {
+        Ok(State::from_existing(
+            Box::new(RecordingMKVS {
+                inner: self.mkvs.boxed_clone(),
+                reads,
+            }),
+            NullBackend,
+            U256::zero(),       /* account_start_nonce */
+            Default::default(), /* factories */
+            None,               /* confidential_ctx */
+        )?)
+    }
+
+    /// Simulate a transaction, with the given changes applied to the state.
+    fn simulate(
+        &self,
+        transaction: &SignedTransaction,
+        overrides: &StateOverride,
+    ) -> Result<Executed, CallError> {
+        let mut state = self.state().map_err(|_| CallError::StateCorrupt)?;
+        self.execute(&mut state, transaction, overrides)
+    }
+
+    /// Simulate a transaction and collect the accounts and storage slots it
+    /// touches.
+    ///
+    /// The sender and the receiver are always accessed, so they are only
+    /// listed for the storage slots they touch. Each slot is taken from the
+    /// storage key that was read, so execution fails if a key does not have
+    /// the layout of a plain storage slot.
+    fn access_list(&self, transaction: &SignedTransaction) -> Result<AccessList, CallError> {
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let mut state = self
+            .recording_state(reads.clone())
+            .map_err(|_| CallError::StateCorrupt)?;
+        let executed = self.execute(&mut state, transaction, &StateOverride::new())?;
+
+        let keys = mem::replace(&mut *reads.lock().unwrap(), Vec::new());
+        let mut accounts: BTreeMap<Address, BTreeSet<H256>> = BTreeMap::new();
+        for key in keys {
+            if key.len() < mem::size_of::<Address>() {
+                continue;
+            }
+            let address = Address::from_slice(&key[..mem::size_of::<Address>()]);
+            let slots = accounts.entry(address).or_default();
+            if is_storage_key(&key) {
+                slots.insert(storage_slot(&key).ok_or(CallError::StateCorrupt)?);
+            }
+        }
+
+        let mut implicit = vec![transaction.sender()];
+        if let Action::Call(to) = transaction.action {
+            implicit.push(to);
+        }
+        for address in implicit {
+            if accounts.get(&address).map_or(false, BTreeSet::is_empty) {
+                accounts.remove(&address);
+            }
+        }
+
+        Ok(AccessList {
+            accounts,
+            gas_used: executed.gas_used,
+            exception: executed.exception,
+            output: executed.output,
+        })
+    }
+
+    /// Execute a transaction on top of the given state.
+    fn execute(
+        &self,
+        state: &mut State<NullBackend>,
+        transaction: &SignedTransaction,
+        overrides: &StateOverride,
+    ) -> Result<Executed, CallError> {
+        for (address, account) in overrides {
+            account.apply(state, address)?;
+        }
+        let machine = genesis::SPEC.engine.machine();
+        let options = TransactOptions::with_no_tracing()
+            .dont_check_nonce()
+            .save_output_from_contract();
+
+        Ok(
+            Executive::new(state, &self.env_info, machine)
+                .transact_virtual(transaction, options)?,
+        )
+    }
+
+    /// Smallest gas limit up to `gas_cap`, or the block gas limit, with which
+    /// a transaction succeeds.
+    ///
+    /// The gas left may change what a contract does, e.g., due to the 63/64
+    /// rule for calls, so the gas used by a single execution is not enough.
+    fn estimate_gas(
+        &self,
+        transaction: &SignedTransaction,
+        gas_cap: Option<U256>,
+    ) -> Result<U256, EstimateGasError> {
+        let sender = transaction.sender();
+        let with_gas = |gas: U256| {
+            Transaction {
+                gas,
+                ..transaction.as_unsigned().clone()
+            }
+            .fake_sign(sender)
+        };
+
+        // All steps run on the same state, so that the accounts and storage
+        // they read are only fetched once.
+        let mut state = self.state().map_err(|_| CallError::StateCorrupt)?;
+        let mut simulate = |gas: U256| {
+            state.checkpoint();
+            let result = self.execute(&mut state, &with_gas(gas), &StateOverride::new());
+            state.revert_to_checkpoint();
+            result
+        };
+
+        // Give up if the transaction fails even with the highest gas limit.
+        let block_gas_limit = U256::from(BLOCK_GAS_LIMIT);
+        let upper = gas_cap.map_or(block_gas_limit, |cap| cap.min(block_gas_limit));
+        let executed = simulate(upper)?;
+        if let Some(exception) = executed.exception {
+            return Err(EstimateGasError::Execution {
+                exception,
+                output: executed.output,
+            });
+        }
+
+        // The transaction always fails below its intrinsic gas.
+        let schedule = genesis::SPEC
+            .engine
+            .machine()
+            .schedule(self.env_info.number);
+        let lower = U256::from(transaction.gas_required(&schedule)) - U256::one();
+
+        let consumed = executed.gas_used + executed.refunded;
+        let succeeds = |gas| match simulate(gas) {
+            Ok(executed) => Ok(executed.exception.is_none()),
+            Err(CallError::Execution(_)) => Ok(false),
+            Err(err) => Err(err),
+        };
+        Ok(search_gas_limit(lower, upper, consumed, succeeds)?)
+    }
+}
+
+/// A wrapper that exposes an Oasis Core block generated by the Eth/WASI runtime
+/// as an Ethereum block.
+pub struct EthereumBlock {
+    snapshot: BlockSnapshot,
+    client: Arc<EthereumRuntimeClient>,
+    cache: Arc<ChainCache>,
+}
+
+impl EthereumBlock {
+    /// Create a new Ethereum block from an Oasis Core block snapshot.
+    pub fn new(
+        snapshot: BlockSnapshot,
+        client: Arc<EthereumRuntimeClient>,
+        cache: Arc<ChainCache>,
+    ) -> Self {
+        Self {
+            snapshot,
+            client,
+            cache,
+        }
+    }
+
+    /// Ethereum block number.
+    pub fn number(&self) -> U256 {
+        self.snapshot.block.header.round.into()
+    }
+
+    /// Ethereum block number as an u64.
+    pub fn number_u64(&self) -> u64 {
+        self.snapshot.block.header.round
+    }
+
+    /// Block hash.
+    pub fn hash(&self) -> H256 {
+        self.snapshot.block_hash.as_ref().into()
+    }
+
+    /// Ethereum state snapshot at given block.
+    pub fn state(&self) -> Result<State<NullBackend>> {
+        self.simulator().state()
+    }
+
+    /// Simulator of transactions on top of this block.
+    fn simulator(&self) -> Simulator {
+        let header = &self.snapshot.block.header;
+        Simulator {
+            mkvs: Box::new(BlockSnapshotMKVS(self.snapshot.clone())),
+            env_info: EnvInfo {
+                number: header.round + 1,
+                author: Default::default(),
+                timestamp: header.timestamp,
+                difficulty: Default::default(),
+                // TODO: Get 256 last hashes.
+                last_hashes: Arc::new(vec![header.previous_hash.as_ref().into()]),
+                gas_used: Default::default(),
+                gas_limit: U256::max_value(),
+            },
+        }
+    }
+
+    /// State of an account and the given storage slots at this block, together
+    /// with MKVS inclusion proofs against the block's state root.
+    pub fn account_proof(
+        &self,
+        storage_client: &StorageClient,
+        address: Address,
+        slots: Vec<H256>,
+    ) -> Result<AccountProof> {
+        let state = self.state()?;
+        let balance = state.balance(&address)?;
+        let nonce = state.nonce(&address)?;
+
+        let mut metadata_key = address.to_vec();
+        metadata_key.extend_from_slice(MKVS_KEY_METADATA);
+        let account = self.key_proof(storage_client, metadata_key)?;
+
+        let mut storage = Vec::with_capacity(slots.len());
+        for slot in slots {
+            // Parity derives the MKVS key of a storage slot internally, so read
+            // the slot from a fresh state and record the storage key it used.
+            let reads = Arc::new(Mutex::new(Vec::new()));
+            let state = self.simulator().recording_state(reads.clone())?;
+            let value = state.storage_at(&address, &slot)?;
+
+            // Nothing is read for slots of accounts which do not exist.
+            let key = reads
+                .lock()
+                .unwrap()
+                .drain(..)
+                .filter(|key| is_storage_key(key))
+                .last();
+            let proof = match key {
+                Some(key) => Some(self.key_proof(storage_client, key)?),
+                None => None,
+            };
+
+            storage.push(StorageProof { slot, value, proof });
+        }
+
+        Ok(AccountProof {
+            state_root: self.snapshot.block.header.state_root.as_ref().into(),
+            balance,
+            nonce,
+            account,
+            storage,
+        })
+    }
+
+    /// MKVS inclusion proof for a key in the state of this block.
+    fn key_proof(&self, storage_client: &StorageClient, key: Vec<u8>) -> Result<KeyProof> {
+        let header = &self.snapshot.block.header;
+        let request = GetRequest {
+            tree: TreeID {
+                root: Root {
+                    namespace: header.namespace,
+                    version: header.round,
+                    root_type: RootType::State,
+                    hash: header.state_root,
+                },
+                position: header.state_root,
+            },
+            key: key.clone(),
+            include_siblings: false,
+        };
+        let proof = storage_client
+            .sync_get(&request, CallOption::default())?
+            .proof;
+
+        // Only return values which are backed by the proof.
+        let value = proof::verify(header.state_root, &key, proof.clone())?;
+
+        Ok(KeyProof { key, value, proof })
+    }
+
+    /// Raw Oasis Core transactions in a block corresponding to Ethereum transactions,
+    /// with their index among all transactions in the block.
+    pub fn raw_transactions(
+        &self,
+    ) -> impl Future<Item = impl Iterator<Item = (u32, TxnCall)>, Error = Error> {
         self.client
             .txn_client()
             .get_txs(
                 self.snapshot.block.header.round,
                 self.snapshot.block.header.io_root,
             )
-            .map(|txns| {
-                txns.0.into_iter().filter_map(|txn| {
-                    let txn: TxnCall = cbor::from_slice(&txn).ok()?;
-                    if txn.method != METHOD_TX {
-                        return None;
-                    }
-
-                    Some(txn)
-                })
-            })
+            .map(|txns| ethereum_calls(txns.0))
     }
 
-    // Ethereum transactions contained in the block.
-    pub fn transactions(
-        &self,
-    ) -> impl Future<Item = impl Iterator<Item = UnverifiedTransaction>, Error = Error> {
-        self.raw_transactions().and_then(|txns| {
-            Ok(txns.filter_map(|txn| {
-                let raw: ByteBuf = cbor::from_value(txn.args).ok()?;
-                let signed: UnverifiedTransaction = rlp::decode(&raw).ok()?;
+    // Ethereum transactions contained in the block, shared with the cache.
+    pub fn transactions(&self) -> BoxFuture<Arc<Vec<UnverifiedTransaction>>> {
+        let round = self.snapshot.block.header.round;
+        match self.cache.block_transactions(round) {
+            Some(txns) => Box::new(future::ok(txns)),
+            None => {
+                let cache = self.cache.clone();
+                Box::new(self.raw_transactions().map(move |txns| {
+                    let txns: Arc<Vec<_>> = Arc::new(
+                        txns.filter_map(|(_, txn)| decode_transaction(txn))
+                            .collect(),
+                    );
+                    cache.insert_block_transactions(round, txns.clone());
+                    txns
+                }))
+            }
+        }
+    }
 
-                Some(signed)
-            }))
+    /// Gas used and gas price of each Ethereum transaction in the block.
+    pub fn gas_usage(&self) -> impl Future<Item = Vec<(U256, U256)>, Error = Error> {
+        let round = self.snapshot.block.header.round;
+        let client = self.client.clone();
+        let cache = self.cache.clone();
+
+        self.raw_transactions().and_then(move |txns| {
+            future::join_all(
+                txns.filter_map(|(index, txn)| Some((index, decode_transaction(txn)?)))
+                    .map(|(index, txn)| {
+                        let gas_price = txn.as_unsigned().gas_price;
+                        // Transactions are fetched by their index among all
+                        // transactions in the block.
+                        fetch_txn(&client, cache.clone(), round, index).map(move |txn| {
+                            // Transactions without a receipt did not use any gas.
+                            let gas_used = txn
+                                .and_then(|txn| txn.receipt().ok())
+                                .map_or(U256::zero(), |receipt| receipt.gas_used);
+                            (gas_used, gas_price)
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            )
         })
     }
 
@@ -712,10 +1588,11 @@ impl EthereumBlock {
             // Either include full localized transactions or just hashes.
             let transactions = if include_txns {
                 EthRpcBlockTransactions::Full(
-                    txns.enumerate()
+                    txns.iter()
+                        .enumerate()
                         .map(|(i, txn)| {
                             EthRpcTransaction::from_localized(LocalizedTransaction {
-                                signed: txn,
+                                signed: txn.clone(),
                                 block_number: header.round,
                                 block_hash: block_hash.as_ref().into(),
                                 transaction_index: i,
@@ -725,7 +1602,7 @@ impl EthereumBlock {
                         .collect(),
                 )
             } else {
-                EthRpcBlockTransactions::Hashes(txns.map(|txn| txn.hash().into()).collect())
+                EthRpcBlockTransactions::Hashes(txns.iter().map(|txn| txn.hash().into()).collect())
             };
 
             // Generate block metadata.
@@ -781,3 +1658,868 @@ impl ethcore::mkvs::MKVS for BlockSnapshotMKVS {
         Box::new(self.clone())
     }
 }
+
+/// Retrieve a block by round, from the cache if possible.
+fn fetch_block(
+    client: Arc<EthereumRuntimeClient>,
+    cache: Arc<ChainCache>,
+    round: u64,
+) -> BoxFuture<Option<EthereumBlock>> {
+    if let Some(snapshot) = cache.block(round) {
+        return Box::new(future::ok(Some(EthereumBlock::new(
+            snapshot, client, cache,
+        ))));
+    }
+
+    Box::new(
+        client
+            .clone()
+            .txn_client()
+            .get_block(round)
+            .map(move |snapshot| {
+                snapshot.map(|snapshot| {
+                    cache.insert_block(&snapshot);
+                    EthereumBlock::new(snapshot, client, cache)
+                })
+            }),
+    )
+}
+
+/// Retrieve the blocks of the given range of rounds (inclusive), from the
+/// cache if possible. At most `MAX_BLOCK_RANGE` blocks can be fetched at once.
+fn fetch_blocks(
+    client: Arc<EthereumRuntimeClient>,
+    cache: Arc<ChainCache>,
+    from: u64,
+    to: u64,
+) -> BoxFuture<Vec<EthereumBlock>> {
+    fetch_range(from, to, move |round| {
+        Box::new(fetch_block(client.clone(), cache.clone(), round))
+    })
+}
+
+/// Fetch the items of the given range of rounds (inclusive) in order, with
+/// at most `BLOCK_FETCH_CONCURRENCY` fetches in flight. Fails if the range
+/// exceeds `MAX_BLOCK_RANGE` rounds or any item is missing.
+fn fetch_range<T, F>(from: u64, to: u64, fetch: F) -> BoxFuture<Vec<T>>
+where
+    T: Send + 'static,
+    F: Fn(u64) -> BoxFuture<Option<T>> + Send + 'static,
+{
+    if to >= from && to - from >= MAX_BLOCK_RANGE {
+        return Box::new(future::err(anyhow!(
+            "block range exceeds {} blocks",
+            MAX_BLOCK_RANGE
+        )));
+    }
+
+    Box::new(
+        stream::iter_ok(from..=to)
+            .map(move |round| {
+                fetch(round).and_then(move |item| match item {
+                    Some(item) => Ok(item),
+                    None => Err(anyhow!("block {} not found", round)),
+                })
+            })
+            .buffered(BLOCK_FETCH_CONCURRENCY)
+            .collect(),
+    )
+}
+
+/// Retrieve a transaction by block round and index, from the cache if
+/// possible.
+fn fetch_txn(
+    client: &EthereumRuntimeClient,
+    cache: Arc<ChainCache>,
+    round: u64,
+    index: u32,
+) -> BoxFuture<Option<Arc<EthereumTransaction>>> {
+    // The latest round moves on, so its transactions are not looked up.
+    if round != ROUND_LATEST {
+        if let Some(txn) = cache.transaction_at(round, index) {
+            return Box::new(future::ok(Some(txn)));
+        }
+    }
+
+    Box::new(
+        client
+            .txn_client()
+            .get_tx(round, index)
+            .map(move |snapshot| snapshot.map(|snapshot| cache_txn(&cache, snapshot))),
+    )
+}
+
+/// Raw transactions of a block which call the Ethereum transaction method,
+/// with their index among all transactions in the block.
+fn ethereum_calls(txns: Vec<Vec<u8>>) -> impl Iterator<Item = (u32, TxnCall)> {
+    txns.into_iter().enumerate().filter_map(|(index, txn)| {
+        let txn: TxnCall = cbor::from_slice(&txn).ok()?;
+        if txn.method != METHOD_TX {
+            return None;
+        }
+
+        Some((index as u32, txn))
+    })
+}
+
+/// Decode the Ethereum transaction submitted by a raw transaction.
+fn decode_transaction(txn: TxnCall) -> Option<UnverifiedTransaction> {
+    let args: TransactionArgs = cbor::from_value(txn.args).ok()?;
+    rlp::decode(args.raw()).ok()
+}
+
+/// Wrap a transaction snapshot and cache it if it is an Ethereum transaction.
+fn cache_txn(cache: &ChainCache, snapshot: TransactionSnapshot) -> Arc<EthereumTransaction> {
+    let txn = Arc::new(EthereumTransaction::new(snapshot));
+    if let Ok(tx) = txn.transaction() {
+        cache.insert_transaction(tx.hash(), txn.clone());
+    }
+    txn
+}
+
+/// MKVS wrapper which records the keys that are read.
+struct RecordingMKVS {
+    inner: Box<dyn ethcore::mkvs::MKVS>,
+    reads: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl ethcore::mkvs::MKVS for RecordingMKVS {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.reads.lock().unwrap().push(key.to_vec());
+        ethcore::mkvs::MKVS::get(&*self.inner, key)
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+        ethcore::mkvs::MKVS::insert(&mut *self.inner, key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        ethcore::mkvs::MKVS::remove(&mut *self.inner, key)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn ethcore::mkvs::MKVS> {
+        Box::new(RecordingMKVS {
+            inner: self.inner.boxed_clone(),
+            reads: self.reads.clone(),
+        })
+    }
+}
+
+/// Smallest gas limit in `(lower, upper]` with which a transaction succeeds.
+///
+/// The transaction must fail with `lower` and succeed with `upper` and any
+/// gas limit above the result. The gas consumed by an execution is passed as
+/// `hint` and tried first: most transactions don't depend on the gas left,
+/// in which case it is the answer and only two steps are needed.
+fn search_gas_limit<F, E>(
+    mut lower: U256,
+    mut upper: U256,
+    hint: U256,
+    mut succeeds: F,
+) -> Result<U256, E>
+where
+    F: FnMut(U256) -> Result<bool, E>,
+{
+    if hint > lower && hint < upper {
+        if succeeds(hint)? {
+            upper = hint;
+            let below = hint - U256::one();
+            if below > lower {
+                if succeeds(below)? {
+                    upper = below;
+                } else {
+                    lower = below;
+                }
+            }
+        } else {
+            lower = hint;
+        }
+    }
+
+    // Invariant: the transaction fails with `lower` and succeeds with `upper`.
+    while upper - lower > U256::one() {
+        let middle = lower + (upper - lower) / 2;
+        if succeeds(middle)? {
+            upper = middle;
+        } else {
+            lower = middle;
+        }
+    }
+
+    Ok(upper)
+}
+
+/// Whether an MKVS key holds contract storage rather than account code or
+/// metadata.
+fn is_storage_key(key: &[u8]) -> bool {
+    let suffix = key.get(mem::size_of::<Address>()..).unwrap_or(&[]);
+    !suffix.starts_with(MKVS_KEY_CODE) && !suffix.starts_with(MKVS_KEY_METADATA)
+}
+
+/// Storage slot which is stored under an MKVS storage key.
+///
+/// Parity stores the storage of plain contracts under the address followed
+/// by the slot, which is also what the runtime prefetches for access lists.
+fn storage_slot(key: &[u8]) -> Option<H256> {
+    if key.len() == mem::size_of::<Address>() + mem::size_of::<H256>() {
+        Some(H256::from_slice(&key[mem::size_of::<Address>()..]))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use oasis_ethwasi_runtime_api::TransactionError;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+    use crate::pending::SubmissionState;
+
+    fn transaction(nonce: u64) -> SignedTransaction {
+        Transaction {
+            nonce: nonce.into(),
+            gas_price: U256::zero(),
+            gas: 21000.into(),
+            action: Action::Call(Address::zero()),
+            value: U256::zero(),
+            data: vec![],
+        }
+        .fake_sign(Address::from(1))
+    }
+
+    fn execution_result() -> ExecutionResult {
+        ExecutionResult {
+            cumulative_gas_used: 21000.into(),
+            gas_used: 21000.into(),
+            log_bloom: Default::default(),
+            logs: vec![],
+            status_code: 1,
+            output: vec![],
+        }
+    }
+
+    /// `tx` method which resolves to the given results in order, together
+    /// with the number of times it was called.
+    fn fake_tx(results: Vec<Result<ExecutionResult>>) -> (TxFn, Arc<AtomicUsize>) {
+        let results = Mutex::new(results.into_iter().collect::<VecDeque<_>>());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let tx: TxFn = Arc::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let result = results
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("no more submissions expected");
+            Box::new(future::result(result))
+        });
+        (tx, calls)
+    }
+
+    fn submission(pending: &Arc<PendingPool>, tx: &TxFn) -> Submission {
+        Submission {
+            tx: tx.clone(),
+            pending: pending.clone(),
+            logger: get_logger("gateway/translator/test"),
+            retry_policy: RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(4),
+                ..Default::default()
+            },
+            retries: 0,
+        }
+    }
+
+    #[test]
+    fn test_send_in_flight() {
+        let pending = Arc::new(PendingPool::new());
+        let (tx, calls) = fake_tx(vec![Ok(execution_result()), Err(anyhow!("invalid nonce"))]);
+        let executed = transaction(0);
+        let failed = transaction(1);
+
+        // The duplicates wait for the submissions in flight.
+        let first = submission(&pending, &tx).send(vec![], executed.clone());
+        let duplicate = submission(&pending, &tx).send(vec![], executed.clone());
+        let (hash, result) = first.wait().unwrap();
+        let (duplicate_hash, duplicate_result) = duplicate.wait().unwrap();
+        assert_eq!(hash, executed.hash());
+        assert_eq!(duplicate_hash, hash);
+        assert_eq!(duplicate_result.gas_used, result.gas_used);
+
+        let first = submission(&pending, &tx).send(vec![], failed.clone());
+        let duplicate = submission(&pending, &tx).send(vec![], failed.clone());
+        assert_eq!(first.wait().unwrap_err().to_string(), "invalid nonce");
+        assert_eq!(duplicate.wait().unwrap_err().to_string(), "invalid nonce");
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_spawn() {
+        let pending = Arc::new(PendingPool::new());
+        let (tx, calls) = fake_tx(vec![Ok(execution_result()), Err(anyhow!("invalid nonce"))]);
+        let executed = transaction(0);
+        let failed = transaction(1);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(|| -> Result<()> {
+                for signed in &[&executed, &executed, &failed] {
+                    let hash = submission(&pending, &tx).spawn(vec![], (*signed).clone());
+                    assert_eq!(hash, signed.hash());
+                }
+
+                // Results are only recorded once the spawned submissions run.
+                for signed in &[&executed, &failed] {
+                    assert_eq!(
+                        pending.status(&signed.hash()),
+                        Some(SubmissionStatus {
+                            state: SubmissionState::Pending,
+                            retries: 0,
+                        })
+                    );
+                }
+                Ok(())
+            }))
+            .unwrap();
+        runtime.run().unwrap();
+
+        // Duplicates are not submitted again.
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        // Results are reported by oasis_getTransactionStatus.
+        assert_eq!(
+            pending.status(&executed.hash()),
+            Some(SubmissionStatus {
+                state: SubmissionState::Executed,
+                retries: 0,
+            })
+        );
+        assert_eq!(
+            pending.status(&failed.hash()),
+            Some(SubmissionStatus {
+                state: SubmissionState::Failed("invalid nonce".to_owned()),
+                retries: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_run_retries() {
+        let pending = Arc::new(PendingPool::new());
+        let (tx, calls) = fake_tx(vec![
+            Err(TransactionError::BlockGasLimitReached.into()),
+            Err(TransactionError::BatchPayloadLimitReached.into()),
+            Ok(execution_result()),
+        ]);
+        let signed = transaction(0);
+        let mut submission = submission(&pending, &tx);
+        submission.retry_policy.initial_backoff = Duration::from_millis(20);
+        submission.retry_policy.max_backoff = Duration::from_millis(40);
+
+        let start = Instant::now();
+        let (hash, _) = Runtime::new()
+            .unwrap()
+            .block_on(submission.send(vec![], signed.clone()))
+            .unwrap();
+        // At least half of each backoff is waited for.
+        assert!(start.elapsed() >= Duration::from_millis(10 + 20));
+
+        assert_eq!(hash, signed.hash());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            pending.status(&hash),
+            Some(SubmissionStatus {
+                state: SubmissionState::Executed,
+                retries: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_run_exhausted() {
+        let pending = Arc::new(PendingPool::new());
+        let (tx, calls) = fake_tx(
+            (0..3)
+                .map(|_| Err(TransactionError::BlockGasLimitReached.into()))
+                .collect(),
+        );
+        let signed = transaction(0);
+        let mut submission = submission(&pending, &tx);
+        submission.retry_policy.max_retries = 2;
+
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(submission.send(vec![], signed.clone()))
+            .unwrap_err();
+        match err.downcast_ref::<TransactionError>() {
+            Some(TransactionError::BlockGasLimitReached) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            pending.status(&signed.hash()),
+            Some(SubmissionStatus {
+                state: SubmissionState::Failed("block gas limit reached".to_owned()),
+                retries: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_run_not_retried() {
+        let pending = Arc::new(PendingPool::new());
+        // Transient errors are not retried by the default policy.
+        let (tx, calls) = fake_tx(vec![Err(grpcio::Error::RpcFailure(
+            grpcio::RpcStatus::new(grpcio::RpcStatusCode::Unavailable, None),
+        )
+        .into())]);
+        let signed = transaction(0);
+
+        assert!(Runtime::new()
+            .unwrap()
+            .block_on(submission(&pending, &tx).send(vec![], signed.clone()))
+            .is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(pending.status(&signed.hash()).unwrap().retries, 0);
+    }
+
+    /// Searches for the smallest gas limit which is at least `threshold` and
+    /// returns it with the number of steps taken.
+    fn search(lower: u64, upper: u64, hint: u64, threshold: u64) -> (U256, usize) {
+        let mut steps = 0;
+        let result = search_gas_limit::<_, ()>(lower.into(), upper.into(), hint.into(), |gas| {
+            steps += 1;
+            Ok(gas >= threshold.into())
+        })
+        .unwrap();
+        (result, steps)
+    }
+
+    #[test]
+    fn test_search_gas_limit() {
+        let (lower, upper) = (20_999, 1_000_000);
+        for &threshold in &[lower + 1, lower + 2, 50_000, upper - 1, upper] {
+            for &hint in &[0, lower + 1, threshold - 1, threshold, threshold + 1, upper] {
+                let (result, steps) = search(lower, upper, hint, threshold);
+                assert_eq!(result, threshold.into(), "hint {}", hint);
+                // Two steps for the hint and a binary search over 2^20 limits.
+                assert!(steps <= 22, "{} steps", steps);
+            }
+        }
+
+        // A correct hint is confirmed without a binary search.
+        assert_eq!(search(lower, upper, 50_000, 50_000), (50_000.into(), 2));
+        // The caller's gas limit is the upper bound.
+        assert_eq!(search(lower, 30_000, 50_000, 25_000).0, 25_000.into());
+
+        let fails = |_| Err("state corrupt");
+        assert_eq!(
+            search_gas_limit(lower.into(), upper.into(), 0.into(), fails),
+            Err("state corrupt")
+        );
+    }
+
+    #[test]
+    fn test_search_gas_limit_63_64() {
+        // The transaction calls a contract which needs `inner` gas, but only
+        // 63/64 of the gas left are passed on. So the transaction fails with
+        // the gas it consumes when it succeeds.
+        let (intrinsic, inner) = (21_000u64, 100_000u64);
+        let succeeds = |gas: U256| -> Result<bool, ()> {
+            let left = gas.low_u64().saturating_sub(intrinsic);
+            Ok(gas.low_u64() >= intrinsic && left - left / 64 >= inner)
+        };
+        let consumed = U256::from(intrinsic + inner);
+        assert!(!succeeds(consumed).unwrap());
+
+        let result = search_gas_limit(
+            U256::from(intrinsic - 1),
+            U256::from(BLOCK_GAS_LIMIT),
+            consumed,
+            succeeds,
+        )
+        .unwrap();
+        assert!(succeeds(result).unwrap());
+        assert!(!succeeds(result - U256::one()).unwrap());
+        assert!(result > consumed);
+    }
+
+    /// MKVS backed by a map which is shared between clones.
+    #[derive(Clone, Default)]
+    struct MemoryMKVS(Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>);
+
+    impl ethcore::mkvs::MKVS for MemoryMKVS {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+
+        fn insert(&mut self, key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+            self.0.lock().unwrap().insert(key.to_vec(), value.to_vec())
+        }
+
+        fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+            self.0.lock().unwrap().remove(key)
+        }
+
+        fn boxed_clone(&self) -> Box<dyn ethcore::mkvs::MKVS> {
+            Box::new(self.clone())
+        }
+    }
+
+    /// Returns the word stored in the slot given by the first word of the
+    /// call data.
+    const LOAD_CODE: &'static [u8] = &[
+        0x60, 0x00, 0x35, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+    ];
+    /// Returns the balance of the caller.
+    const BALANCE_CODE: &'static [u8] =
+        &[0x33, 0x31, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+    /// Returns 42.
+    const CONSTANT_CODE: &'static [u8] =
+        &[0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+
+    /// Reads the balance of `Address::from(20)`, then copies slot 0 to slot 1.
+    const COPY_CODE: &'static [u8] = &[
+        0x60, 0x14, 0x31, 0x50, 0x60, 0x00, 0x54, 0x60, 0x01, 0x55, 0x00,
+    ];
+
+    /// Simulator on top of a state populated by `populate`.
+    fn simulator<F>(populate: F) -> Simulator
+    where
+        F: FnOnce(&mut State<NullBackend>),
+    {
+        let mkvs = MemoryMKVS::default();
+        let mut state = State::from_existing(
+            Box::new(mkvs.clone()),
+            NullBackend,
+            U256::zero(),
+            Default::default(),
+            None,
+        )
+        .unwrap();
+        populate(&mut state);
+        state.commit().unwrap();
+
+        Simulator {
+            mkvs: Box::new(mkvs),
+            env_info: EnvInfo {
+                number: 1,
+                gas_limit: U256::max_value(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Deploys `code` at `address` with the given storage.
+    fn deploy(
+        state: &mut State<NullBackend>,
+        address: Address,
+        code: &[u8],
+        storage: &[(u64, u64)],
+    ) {
+        state.new_contract(&address, U256::zero(), U256::zero(), u64::max_value());
+        state.init_code(&address, code.to_vec()).unwrap();
+        for &(key, value) in storage {
+            state
+                .set_storage(&address, H256::from(key), H256::from(value))
+                .unwrap();
+        }
+    }
+
+    /// Calls a contract from `Address::from(1)` with the given changes to the
+    /// state and returns the word it returns.
+    fn call(simulator: &Simulator, to: Address, data: u64, overrides: &StateOverride) -> U256 {
+        let transaction = Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas: 1_000_000.into(),
+            action: Action::Call(to),
+            value: U256::zero(),
+            data: H256::from(data).to_vec(),
+        }
+        .fake_sign(Address::from(1));
+        let executed = simulator.simulate(&transaction, overrides).unwrap();
+        assert!(executed.exception.is_none());
+        U256::from(&executed.output[..])
+    }
+
+    fn overrides(address: Address, account: AccountOverride) -> StateOverride {
+        let mut overrides = StateOverride::new();
+        overrides.insert(address, account);
+        overrides
+    }
+
+    #[test]
+    fn test_override_balance() {
+        let (sender, contract) = (Address::from(1), Address::from(10));
+        let simulator = simulator(|state| {
+            state
+                .add_balance(&sender, &1000.into(), CleanupMode::NoEmpty)
+                .unwrap();
+            deploy(state, contract, BALANCE_CODE, &[]);
+        });
+
+        assert_eq!(
+            call(&simulator, contract, 0, &StateOverride::new()),
+            1000.into()
+        );
+        for &balance in &[0, 5, 1_000_000] {
+            let balance = U256::from(balance);
+            let overrides = overrides(
+                sender,
+                AccountOverride {
+                    balance: Some(balance),
+                    ..Default::default()
+                },
+            );
+            assert_eq!(call(&simulator, contract, 0, &overrides), balance);
+        }
+    }
+
+    #[test]
+    fn test_override_nonce() {
+        let sender = Address::from(1);
+        let simulator = simulator(|state| {
+            state.inc_nonce(&sender).unwrap();
+        });
+        let nonce = |account: AccountOverride| -> Result<U256, CallError> {
+            let mut state = simulator.state().unwrap();
+            account.apply(&mut state, &sender)?;
+            Ok(state.nonce(&sender).unwrap())
+        };
+
+        let raise = |nonce: u64| AccountOverride {
+            nonce: Some(nonce.into()),
+            ..Default::default()
+        };
+        assert_eq!(nonce(raise(1)).unwrap(), 1.into());
+        assert_eq!(nonce(raise(5)).unwrap(), 5.into());
+        let max = 1 + MAX_NONCE_OVERRIDE_STEPS;
+        assert_eq!(nonce(raise(max)).unwrap(), max.into());
+
+        // Beyond the limit, the nonce can only be changed together with the
+        // storage.
+        for &new in &[0, max + 1] {
+            match nonce(raise(new)) {
+                Err(CallError::Execution(ExecutionError::Internal(message))) => assert!(
+                    message.contains("must be between 1 and 65537"),
+                    "{}",
+                    message
+                ),
+                result => panic!("unexpected result: {:?}", result),
+            }
+            let replaced = AccountOverride {
+                nonce: Some(new.into()),
+                storage: Some(HashMap::new()),
+                ..Default::default()
+            };
+            assert_eq!(nonce(replaced).unwrap(), new.into());
+        }
+    }
+
+    #[test]
+    fn test_override_code() {
+        let contract = Address::from(10);
+        let simulator = simulator(|state| deploy(state, contract, LOAD_CODE, &[(0, 7)]));
+
+        assert_eq!(
+            call(&simulator, contract, 0, &StateOverride::new()),
+            7.into()
+        );
+        let overrides = overrides(
+            contract,
+            AccountOverride {
+                code: Some(CONSTANT_CODE.to_vec()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(call(&simulator, contract, 0, &overrides), 42.into());
+
+        // Code can be given to accounts which do not exist yet.
+        let empty = Address::from(11);
+        let overrides = overrides(
+            empty,
+            AccountOverride {
+                code: Some(CONSTANT_CODE.to_vec()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(call(&simulator, empty, 0, &overrides), 42.into());
+    }
+
+    #[test]
+    fn test_override_storage() {
+        let contract = Address::from(10);
+        let simulator = simulator(|state| {
+            deploy(state, contract, LOAD_CODE, &[(0, 1), (1, 2)]);
+            state
+                .add_balance(&contract, &1000.into(), CleanupMode::NoEmpty)
+                .unwrap();
+        });
+        let slots = |slots: &[(u64, u64)]| -> HashMap<H256, H256> {
+            slots
+                .iter()
+                .map(|&(key, value)| (H256::from(key), H256::from(value)))
+                .collect()
+        };
+
+        // stateDiff only changes the given slots.
+        let diff = overrides(
+            contract,
+            AccountOverride {
+                storage_diff: slots(&[(1, 9), (2, 3)]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(call(&simulator, contract, 0, &diff), 1.into());
+        assert_eq!(call(&simulator, contract, 1, &diff), 9.into());
+        assert_eq!(call(&simulator, contract, 2, &diff), 3.into());
+
+        // state clears all other slots, but keeps the code and the balance.
+        let replaced = overrides(
+            contract,
+            AccountOverride {
+                storage: Some(slots(&[(1, 9)])),
+                ..Default::default()
+            },
+        );
+        assert_eq!(call(&simulator, contract, 0, &replaced), 0.into());
+        assert_eq!(call(&simulator, contract, 1, &replaced), 9.into());
+        let mut state = simulator.state().unwrap();
+        replaced[&contract].apply(&mut state, &contract).unwrap();
+        assert_eq!(state.balance(&contract).unwrap(), 1000.into());
+
+        // The overrides only apply to a single simulation.
+        assert_eq!(
+            call(&simulator, contract, 1, &StateOverride::new()),
+            2.into()
+        );
+    }
+
+    #[test]
+    fn test_access_list() {
+        let (sender, contract, other) = (Address::from(1), Address::from(10), Address::from(20));
+        let simulator = simulator(|state| {
+            deploy(state, contract, COPY_CODE, &[(0, 7)]);
+            state
+                .add_balance(&other, &1.into(), CleanupMode::NoEmpty)
+                .unwrap();
+        });
+        let transaction = Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas: 1_000_000.into(),
+            action: Action::Call(contract),
+            value: U256::zero(),
+            data: vec![],
+        }
+        .fake_sign(sender);
+
+        let access_list = simulator.access_list(&transaction).unwrap();
+        assert!(access_list.exception.is_none());
+        let slots: BTreeSet<H256> = vec![H256::from(0), H256::from(1)].into_iter().collect();
+        let mut expected = BTreeMap::new();
+        expected.insert(contract, slots);
+        expected.insert(other, BTreeSet::new());
+        // The sender touches no storage, so it is left out.
+        assert_eq!(access_list.accounts, expected);
+
+        // Simulating does not change the state.
+        let state = simulator.state().unwrap();
+        assert_eq!(
+            state.storage_at(&contract, &H256::from(1)).unwrap(),
+            H256::zero()
+        );
+    }
+
+    #[test]
+    fn test_storage_slot() {
+        let mut key = Address::from(10).to_vec();
+        key.extend_from_slice(&H256::from(3));
+        assert_eq!(storage_slot(&key), Some(H256::from(3)));
+        assert_eq!(storage_slot(&key[1..]), None);
+    }
+
+    #[test]
+    fn test_ethereum_calls() {
+        let call = |method: &str, nonce: u64| {
+            let raw = rlp::encode(&*transaction(nonce)).into_vec();
+            cbor::to_vec(&TxnCall {
+                method: method.to_owned(),
+                args: cbor::to_value(TransactionArgs::Raw(ByteBuf::from(raw))),
+            })
+        };
+        let txns = vec![
+            call("other", 0),
+            call(METHOD_TX, 1),
+            b"garbage".to_vec(),
+            call(METHOD_TX, 2),
+        ];
+
+        // Indices count all transactions in the block.
+        let calls: Vec<_> = ethereum_calls(txns).collect();
+        assert_eq!(
+            calls.iter().map(|&(index, _)| index).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        let nonces: Vec<_> = calls
+            .into_iter()
+            .filter_map(|(_, txn)| decode_transaction(txn))
+            .map(|txn| txn.as_unsigned().nonce)
+            .collect();
+        assert_eq!(nonces, vec![1.into(), 2.into()]);
+    }
+
+    #[test]
+    fn test_fetch_range() {
+        let fetched = Arc::new(Mutex::new(vec![]));
+        let fetch = {
+            let fetched = fetched.clone();
+            move |round: u64| -> BoxFuture<Option<u64>> {
+                fetched.lock().unwrap().push(round);
+                if round == 300 {
+                    return Box::new(future::ok(None));
+                }
+                // Later rounds finish first, but are returned in order.
+                let delay = Duration::from_millis(20 - round % 10);
+                Box::new(
+                    Delay::new(Instant::now() + delay)
+                        .map_err(Error::from)
+                        .map(move |()| Some(round)),
+                )
+            }
+        };
+        let mut runtime = Runtime::new().unwrap();
+
+        let rounds = runtime.block_on(fetch_range(1, 9, fetch.clone())).unwrap();
+        assert_eq!(rounds, (1..=9).collect::<Vec<_>>());
+        assert!(runtime
+            .block_on(fetch_range(5, 4, fetch.clone()))
+            .unwrap()
+            .is_empty());
+
+        // Missing blocks fail the whole range.
+        assert!(runtime
+            .block_on(fetch_range(298, 302, fetch.clone()))
+            .is_err());
+
+        // Ranges which are too large are rejected without fetching anything.
+        fetched.lock().unwrap().clear();
+        assert!(runtime
+            .block_on(fetch_range(1, MAX_BLOCK_RANGE + 1, fetch.clone()))
+            .is_err());
+        assert!(fetched.lock().unwrap().is_empty());
+        assert!(runtime
+            .block_on(fetch_range(1, MAX_BLOCK_RANGE, fetch))
+            .is_ok());
+        assert_eq!(fetched.lock().unwrap().len(), MAX_BLOCK_RANGE as usize);
+    }
+}
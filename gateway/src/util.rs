@@ -1,7 +1,7 @@
 use std::fmt;
 
 use anyhow::Error;
-use ethcore::ids::BlockId;
+use ethcore::{ids::BlockId, vm::Error as VmError};
 use ethereum_types::U256;
 use jsonrpc_core::{self, ErrorCode, Value};
 use parity_rpc::v1::{helpers::errors::codes, types::BlockNumber};
@@ -39,3 +39,126 @@ pub fn execution_error<T: fmt::Display>(data: T) -> jsonrpc_core::Error {
         data: Some(Value::String(format!("{}", data))),
     }
 }
+
+/// ABI selector of the Solidity `Error(string)` revert payload.
+const REVERT_REASON_SELECTOR: &'static [u8] = &[0x08, 0xc3, 0x79, 0xa0];
+
+/// Decodes the reason from a Solidity `Error(string)` revert payload.
+pub fn revert_reason(output: &[u8]) -> Option<String> {
+    if !output.starts_with(REVERT_REASON_SELECTOR) {
+        return None;
+    }
+    let data = &output[REVERT_REASON_SELECTOR.len()..];
+
+    // Reads a 32-byte word as an offset or length into `data`.
+    let read_usize = |at: usize| -> Option<usize> {
+        let word = data.get(at..at.checked_add(32)?)?;
+        let value = U256::from(word);
+        if value > U256::from(data.len()) {
+            return None;
+        }
+        Some(value.low_u64() as usize)
+    };
+
+    let offset = read_usize(0)?;
+    let length = read_usize(offset)?;
+    let start = offset + 32;
+    let reason = data.get(start..start.checked_add(length)?)?;
+    String::from_utf8(reason.to_vec()).ok()
+}
+
+/// Constructs a JSON-RPC error for a failed execution, with the revert reason
+/// in the message if the contract provided one and the raw output as data.
+pub fn vm_error(exception: &VmError, output: &[u8]) -> jsonrpc_core::Error {
+    let message = match revert_reason(output) {
+        Some(reason) => format!("execution reverted: {}", reason),
+        None => format!("execution failed: {}", exception),
+    };
+    let data: String = output.iter().map(|b| format!("{:02x}", b)).collect();
+
+    jsonrpc_core::Error {
+        code: ErrorCode::ServerError(codes::EXECUTION_ERROR),
+        message,
+        data: Some(Value::String(format!("0x{}", data))),
+    }
+}
+
+/// Rewards at the given percentiles of the gas used in a block, given the gas
+/// used and the reward of each of its transactions.
+///
+/// Percentiles must be sorted in ascending order.
+pub fn reward_percentiles(mut txns: Vec<(U256, U256)>, percentiles: &[f64]) -> Vec<U256> {
+    if txns.is_empty() {
+        return vec![U256::zero(); percentiles.len()];
+    }
+
+    txns.sort_by_key(|&(_, reward)| reward);
+    let total_gas = txns.iter().fold(0u64, |sum, &(gas_used, _)| {
+        sum.saturating_add(gas_used.low_u64())
+    });
+
+    let mut index = 0;
+    let mut gas = txns[0].0.low_u64();
+    percentiles
+        .iter()
+        .map(|percentile| {
+            let threshold = total_gas as f64 * percentile / 100.0;
+            while (gas as f64) < threshold && index < txns.len() - 1 {
+                index += 1;
+                gas = gas.saturating_add(txns[index].0.low_u64());
+            }
+            txns[index].1
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reward_percentiles() {
+        let txns = vec![
+            (U256::from(50_000), U256::from(3)),
+            (U256::from(21_000), U256::from(1)),
+            (U256::from(29_000), U256::from(2)),
+        ];
+        assert_eq!(
+            reward_percentiles(txns, &[0.0, 21.0, 25.0, 50.0, 51.0, 100.0]),
+            vec![1, 1, 2, 2, 3, 3]
+                .into_iter()
+                .map(U256::from)
+                .collect::<Vec<_>>()
+        );
+
+        // Empty blocks have no rewards.
+        assert_eq!(
+            reward_percentiles(vec![], &[10.0, 90.0]),
+            vec![U256::zero(); 2]
+        );
+    }
+
+    #[test]
+    fn test_revert_reason() {
+        // Error("Not enough Ether provided.")
+        let mut output = REVERT_REASON_SELECTOR.to_vec();
+        let mut word = [0u8; 32];
+        word[31] = 0x20;
+        output.extend_from_slice(&word);
+        word[31] = 26;
+        output.extend_from_slice(&word);
+        output.extend_from_slice(b"Not enough Ether provided.");
+        output.extend_from_slice(&[0; 6]);
+
+        assert_eq!(
+            revert_reason(&output),
+            Some("Not enough Ether provided.".to_owned())
+        );
+
+        // Truncated payloads and other outputs have no reason.
+        assert_eq!(revert_reason(&output[..40]), None);
+        assert_eq!(revert_reason(&output[..80]), None);
+        assert_eq!(revert_reason(b""), None);
+        assert_eq!(revert_reason(&[0xde, 0xad, 0xbe, 0xef]), None);
+    }
+}
@@ -15,11 +15,11 @@ use ethcore::spec::Spec;
 use io_context::Context;
 use oasis_core_runtime::storage::{
     mkvs::{sync::NoopReadSyncer, OverlayTree, RootType, Tree},
-    StorageContext,
+    KeyValue, StorageContext,
 };
 use oasis_ethwasi_runtime_common::{
     parity::NullBackend,
-    storage::{MemoryKeyValue, ThreadLocalMKVS},
+    storage::{FileKeyValue, MemoryKeyValue, ThreadLocalMKVS},
 };
 
 fn main() {
@@ -38,6 +38,12 @@ fn main() {
                 .takes_value(true)
                 .required(true),
         )
+        .arg(
+            Arg::with_name("untrusted_local")
+                .long("untrusted-local")
+                .help("Persist untrusted local storage to the given file")
+                .takes_value(true),
+        )
         .get_matches();
 
     // Load Ethereum genesis state.
@@ -45,7 +51,12 @@ fn main() {
     let spec = Spec::load(eth_genesis).expect("failed to load Ethereum genesis file");
 
     // Populate MKVS with state required at genesis.
-    let untrusted_local = Arc::new(MemoryKeyValue::new());
+    let untrusted_local: Arc<dyn KeyValue> = match matches.value_of("untrusted_local") {
+        Some(path) => {
+            Arc::new(FileKeyValue::open(path).expect("failed to open untrusted local storage"))
+        }
+        None => Arc::new(MemoryKeyValue::new()),
+    };
     let mut mkvs = Tree::make()
         .with_capacity(0, 0)
         .with_root_type(RootType::State)
@@ -0,0 +1,139 @@
+//! Generates the trusted key manager policy signers from the signer manifest.
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fs,
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+
+/// Default location of the signer manifest (relative to this crate).
+const DEFAULT_MANIFEST: &str = "../resources/keymanager/policy_signers.json";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    threshold: u64,
+    signers: Vec<Signer>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+enum Signer {
+    /// Hex-encoded Ed25519 public key.
+    PublicKey(String),
+    /// Seed used to derive an (insecure) test key.
+    TestSeed(String),
+}
+
+fn main() {
+    for var in &[
+        "OASIS_KM_POLICY_SIGNERS_MANIFEST",
+        "OASIS_KM_POLICY_SIGNERS_PROFILE",
+        "OASIS_UNSAFE_KM_POLICY_KEYS",
+        "OASIS_UNSAFE_SKIP_KM_POLICY",
+    ] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+
+    let manifest_path = env::var("OASIS_KM_POLICY_SIGNERS_MANIFEST")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join(DEFAULT_MANIFEST)
+        });
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
+
+    let unsafe_keys = env::var_os("OASIS_UNSAFE_KM_POLICY_KEYS").is_some();
+    let skip_policy = env::var_os("OASIS_UNSAFE_SKIP_KM_POLICY").is_some();
+    let explicit_profile = env::var("OASIS_KM_POLICY_SIGNERS_PROFILE").ok();
+    let profile_name = explicit_profile.clone().unwrap_or_else(|| {
+        if unsafe_keys {
+            "testing".to_owned()
+        } else {
+            "production".to_owned()
+        }
+    });
+
+    let raw_manifest = fs::read_to_string(&manifest_path).unwrap_or_else(|err| {
+        panic!(
+            "failed to read policy signer manifest {}: {}",
+            manifest_path.display(),
+            err
+        )
+    });
+    let mut profiles: BTreeMap<String, Profile> = serde_json::from_str(&raw_manifest)
+        .unwrap_or_else(|err| {
+            panic!(
+                "malformed policy signer manifest {}: {}",
+                manifest_path.display(),
+                err
+            )
+        });
+    let profile = profiles.remove(&profile_name).unwrap_or_else(|| {
+        panic!(
+            "unknown policy signer profile \"{}\" (available: {})",
+            profile_name,
+            profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+        )
+    });
+
+    let mut seen = HashSet::new();
+    let mut signers = Vec::with_capacity(profile.signers.len());
+    for signer in &profile.signers {
+        let entry = match signer {
+            Signer::PublicKey(key) => {
+                let key = hex::decode(key.trim_start_matches("0x"))
+                    .ok()
+                    .filter(|key| key.len() == 32)
+                    .unwrap_or_else(|| panic!("invalid policy signer public key: {}", key));
+                format!("PolicySigner::PublicKey({:?})", key)
+            }
+            Signer::TestSeed(seed) => {
+                if !unsafe_keys {
+                    panic!(
+                        "profile \"{}\" uses test keys, but OASIS_UNSAFE_KM_POLICY_KEYS is not set",
+                        profile_name
+                    );
+                }
+                format!("PolicySigner::TestSeed({:?})", seed)
+            }
+        };
+        if !seen.insert(entry.clone()) {
+            panic!(
+                "duplicate signer in policy signer profile \"{}\"",
+                profile_name
+            );
+        }
+        signers.push(entry);
+    }
+
+    if profile.threshold == 0 || profile.threshold > signers.len() as u64 {
+        let msg = format!(
+            "policy signer profile \"{}\" cannot meet its threshold ({} of {} signers)",
+            profile_name,
+            profile.threshold,
+            signers.len()
+        );
+        // A policy that is never enforced does not need to be satisfiable. Test
+        // builds may fall back to a profile that does not list its signers yet,
+        // but a profile that was asked for must always be satisfiable.
+        if !skip_policy && (explicit_profile.is_some() || !unsafe_keys) {
+            panic!("{}", msg);
+        }
+        println!("cargo:warning={}", msg);
+    }
+
+    let generated = format!(
+        "/// Name of the policy signer profile selected at build time.\n\
+         const PROFILE: &str = {:?};\n\
+         /// Number of signatures required for a key manager policy.\n\
+         const THRESHOLD: u64 = {};\n\
+         /// Key manager policy signers.\n\
+         const SIGNERS: &[PolicySigner] = &[{}];\n",
+        profile_name,
+        profile.threshold,
+        signers.join(", ")
+    );
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("policy_signers.rs");
+    fs::write(&out_path, generated).expect("failed to write generated policy signers");
+}
@@ -1,27 +1,44 @@
 use oasis_core_keymanager_api_common::*;
-use oasis_core_runtime::common::crypto::signature::PrivateKey as OasisPrivateKey;
+use oasis_core_runtime::common::crypto::signature::{
+    PrivateKey as OasisPrivateKey, PublicKey as OasisPublicKey,
+};
 use std::collections::HashSet;
 
-pub fn trusted_policy_signers() -> TrustedPolicySigners {
-    TrustedPolicySigners {
-        signers: {
-            let mut set = HashSet::new();
-            if option_env!("OASIS_UNSAFE_KM_POLICY_KEYS").is_some() {
-                for seed in [
-                    "ekiden key manager test multisig key 0",
-                    "ekiden key manager test multisig key 1",
-                    "ekiden key manager test multisig key 2",
-                ]
-                .iter()
-                {
-                    let private_key = OasisPrivateKey::from_test_seed(seed.to_string());
-                    set.insert(private_key.public_key());
-                }
+/// Key manager policy signer, as listed in the signer manifest.
+// Which variants are constructed depends on the selected profile.
+#[allow(dead_code)]
+enum PolicySigner {
+    /// Raw Ed25519 public key.
+    PublicKey([u8; 32]),
+    /// Seed of an insecure test key (`OASIS_UNSAFE_KM_POLICY_KEYS` builds only).
+    TestSeed(&'static str),
+}
+
+// Generated by build.rs from resources/keymanager/policy_signers.json.
+include!(concat!(env!("OUT_DIR"), "/policy_signers.rs"));
+
+impl PolicySigner {
+    fn public_key(&self) -> OasisPublicKey {
+        match self {
+            PolicySigner::PublicKey(key) => OasisPublicKey::from(&key[..]),
+            PolicySigner::TestSeed(seed) => {
+                OasisPrivateKey::from_test_seed(seed.to_string()).public_key()
             }
+        }
+    }
+}
+
+/// Name of the policy signer profile the runtime was built with.
+pub fn policy_signers_profile() -> &'static str {
+    PROFILE
+}
 
-            // TODO: Populate with the production keys as well.
-            set
-        },
-        threshold: 9001, // TODO: Set this to a real value.
+pub fn trusted_policy_signers() -> TrustedPolicySigners {
+    TrustedPolicySigners {
+        signers: SIGNERS
+            .iter()
+            .map(PolicySigner::public_key)
+            .collect::<HashSet<_>>(),
+        threshold: THRESHOLD,
     }
 }
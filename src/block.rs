@@ -5,33 +5,69 @@ use ethereum_types::{H256, U256};
 use io_context::Context as IoContext;
 use oasis_core_keymanager_client::KeyManagerClient;
 use oasis_core_runtime::{
-    common::logger::get_logger, runtime_context, transaction::Context as TxnContext,
+    common::{crypto::hash::Hash, logger::get_logger},
+    runtime_context,
+    transaction::{context::NoRuntimeContext, Context as TxnContext},
 };
 use oasis_ethwasi_runtime_common::{
-    confidential::ConfidentialCtx, genesis, parity::NullBackend, storage::ThreadLocalMKVS,
+    confidential::ConfidentialCtx,
+    genesis,
+    parity::{CachedBackend, StateCache, DEFAULT_ACCOUNT_CACHE_SIZE, DEFAULT_CODE_CACHE_SIZE},
+    storage::{StorageStatsRecorder, ThreadLocalMKVS},
+    SizeLimits,
 };
-use slog::{info, Logger};
+use slog::{info, warn, Logger};
 use std::{collections::HashSet, sync::Arc};
 
 pub struct BlockContext {
     /// Logger.
     pub logger: Logger,
     /// Ethereum state for the current batch.
-    pub state: State<NullBackend>,
+    pub state: State<CachedBackend>,
     /// Environment info for the current batch.
     pub env_info: EnvInfo,
     /// Set of executed transactions.
     pub transaction_set: HashSet<H256>,
+    /// Storage I/O performed by the current batch.
+    pub storage_stats: Arc<StorageStatsRecorder>,
+    /// Size limits enforced on transactions.
+    pub size_limits: SizeLimits,
 }
 
 /// Oasis runtime batch handler.
 pub struct OasisBatchHandler {
     key_manager: Arc<dyn KeyManagerClient>,
+    /// Account and code cache shared across batches.
+    state_cache: Arc<StateCache>,
+    /// Size limits enforced on transactions and batches.
+    size_limits: SizeLimits,
 }
 
 impl OasisBatchHandler {
     pub fn new(key_manager: Arc<dyn KeyManagerClient>) -> Self {
-        Self { key_manager }
+        Self {
+            key_manager,
+            state_cache: Arc::new(StateCache::new(
+                DEFAULT_ACCOUNT_CACHE_SIZE,
+                DEFAULT_CODE_CACHE_SIZE,
+            )),
+            size_limits: SizeLimits::default(),
+        }
+    }
+
+    /// Size limits enforced on transactions and batches.
+    pub fn size_limits(&self) -> &SizeLimits {
+        &self.size_limits
+    }
+
+    /// Overrides the default size limits.
+    pub fn set_size_limits(&mut self, size_limits: SizeLimits) {
+        self.size_limits = size_limits;
+    }
+
+    /// Replaces the shared state cache with an empty one of the given sizes.
+    pub fn set_state_cache_sizes(&mut self, account_cache_size: usize, code_cache_size: usize) {
+        self.state_cache = Arc::new(StateCache::new(account_cache_size, code_cache_size));
     }
 
     pub fn start_batch(&self, ctx: &mut TxnContext) {
@@ -39,10 +75,18 @@ impl OasisBatchHandler {
 
         info!(logger, "Computing new block"; "round" => ctx.header.round + 1);
 
-        // Initialize Ethereum state access functions.
+        // Initialize Ethereum state access functions. The storage keys read by
+        // each transaction are the prefetch hints for the next batch.
+        #[cfg(feature = "prefetch")]
+        let storage_stats = Arc::new(StorageStatsRecorder::with_key_log());
+        #[cfg(not(feature = "prefetch"))]
+        let storage_stats = Arc::new(StorageStatsRecorder::new());
         let state = State::from_existing(
-            Box::new(ThreadLocalMKVS::new(IoContext::create_child(&ctx.io_ctx))),
-            NullBackend,
+            Box::new(ThreadLocalMKVS::with_stats(
+                IoContext::create_child(&ctx.io_ctx),
+                storage_stats.clone(),
+            )),
+            CachedBackend::new(self.state_cache.clone(), ctx.header.state_root),
             U256::zero(),       /* account_start_nonce */
             Default::default(), /* factories */
             Some(Box::new(ConfidentialCtx::new(
@@ -70,6 +114,8 @@ impl OasisBatchHandler {
             state,
             env_info,
             transaction_set: HashSet::new(),
+            storage_stats,
+            size_limits: self.size_limits,
         });
     }
 
@@ -78,6 +124,57 @@ impl OasisBatchHandler {
         FundManager::try_unlock(ctx.header.timestamp as i64, &mut ectx.state);
         info!(ectx.logger, "Commiting state into storage");
         ectx.state.commit().expect("state commit must succeed");
-        info!(ectx.logger, "Block finalized");
+
+        // Hand the accounts touched by this batch over to the shared cache.
+        let BlockContext {
+            logger,
+            state,
+            storage_stats,
+            ..
+        } = Self::take_block_context(ctx);
+        let (_, backend) = state.drop();
+        self.state_cache.merge(backend, !ctx.check_only);
+
+        let stats = self.state_cache.stats();
+        let io = storage_stats.snapshot();
+        let total = io.total();
+        info!(logger, "Block finalized";
+            "account_cache_hit_rate" => stats.account_hit_rate(),
+            "code_cache_hit_rate" => stats.code_hit_rate(),
+            "storage_reads" => total.reads,
+            "storage_read_bytes" => total.read_bytes,
+            "storage_writes" => total.writes,
+            "storage_write_bytes" => total.write_bytes,
+            "storage_removes" => total.removes,
+            "code_reads" => io.code.reads,
+            "metadata_reads" => io.metadata.reads,
+            "metadata_writes" => io.metadata.writes,
+            "slot_reads" => io.storage.reads,
+            "slot_writes" => io.storage.writes
+        );
+    }
+
+    /// Aborts the current batch, discarding all of its state changes.
+    ///
+    /// Parity only writes to storage when the state is committed, so dropping
+    /// the uncommitted state rolls back every transaction of the batch.
+    pub fn abort_batch(&self, ctx: &mut TxnContext) {
+        let BlockContext { logger, state, .. } = Self::take_block_context(ctx);
+        let (_, backend) = state.drop();
+        // Only accounts which were not modified are still valid.
+        self.state_cache.merge(backend, false);
+
+        warn!(logger, "Batch aborted, discarding state changes");
+    }
+
+    /// Called once the new state root of an executed batch is known.
+    pub fn finalize(&self, new_state_root: Hash) {
+        self.state_cache.finalize(new_state_root);
+    }
+
+    fn take_block_context(ctx: &mut TxnContext) -> BlockContext {
+        *std::mem::replace(&mut ctx.runtime, Box::new(NoRuntimeContext))
+            .downcast::<BlockContext>()
+            .expect("runtime context must be a BlockContext")
     }
 }
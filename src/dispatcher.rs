@@ -1,21 +1,22 @@
+#[cfg(feature = "prefetch")]
+use std::collections::{HashMap, HashSet};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
 
-use anyhow::{anyhow, bail, Context as AnyContext, Error as AnyError, Result};
+use anyhow::{bail, Context as AnyContext, Error as AnyError, Result};
 use ethcore::transaction::SignedTransaction;
 #[cfg(feature = "prefetch")]
 use ethcore::{
     state::{MKVS_KEY_CODE, MKVS_KEY_METADATA},
     transaction::Action,
 };
-use serde_bytes::ByteBuf;
 use thiserror::Error;
 
-use oasis_core_keymanager_client::KeyManagerClient;
 #[cfg(feature = "prefetch")]
-use oasis_core_runtime::storage::mkvs::Prefix;
+use ethereum_types::Address;
+use oasis_core_keymanager_client::KeyManagerClient;
 use oasis_core_runtime::{
     common::{cbor, crypto::hash::Hash},
     consensus::roothash::Message as RoothashMessage,
@@ -29,31 +30,48 @@ use oasis_core_runtime::{
     },
     types::{Error as RuntimeError, *},
 };
+#[cfg(feature = "prefetch")]
+use oasis_core_runtime::{runtime_context, storage::mkvs::Prefix};
 
+#[cfg(feature = "prefetch")]
+use super::{block::BlockContext, prefetch::PrefetchHints};
 use super::{
     block::OasisBatchHandler,
     methods::{check, execute},
 };
 
-use oasis_ethwasi_runtime_api as api;
+use oasis_ethwasi_runtime_api::{self as api, AccessListItem, TransactionArgs, TransactionError};
+use oasis_ethwasi_runtime_common::SizeLimits;
 
 /// Dispatch error.
 #[derive(Error, Debug)]
 enum DispatchError {
     #[error("method not found: {method}")]
     MethodNotFound { method: String },
+    #[error("batch aborted")]
+    BatchAborted,
 }
 
 pub struct DecodedCall {
     pub transaction: SignedTransaction,
+    /// Access list submitted with the transaction.
+    pub access_list: Vec<AccessListItem>,
 }
 
 pub struct Dispatcher {
     /// Registered batch handler.
     batch_handler: OasisBatchHandler,
     /// Abort batch flag.
-    /// Aborting not implemented.
     abort_batch: Option<Arc<AtomicBool>>,
+    /// Hook invoked with the index of each transaction before it is executed.
+    #[cfg(test)]
+    before_tx_hook: Option<Box<dyn Fn(usize) + Send + Sync>>,
+    /// Whether storage is prefetched before executing a batch.
+    #[cfg(feature = "prefetch")]
+    prefetch: bool,
+    /// Storage keys read by previous calls to each contract.
+    #[cfg(feature = "prefetch")]
+    prefetch_hints: PrefetchHints,
 }
 
 impl Dispatcher {
@@ -62,10 +80,116 @@ impl Dispatcher {
         Dispatcher {
             batch_handler: OasisBatchHandler::new(key_manager),
             abort_batch: None,
+            #[cfg(test)]
+            before_tx_hook: None,
+            #[cfg(feature = "prefetch")]
+            prefetch: true,
+            #[cfg(feature = "prefetch")]
+            prefetch_hints: PrefetchHints::new(),
         }
     }
 
+    /// Overrides the default transaction and batch size limits.
+    pub fn set_size_limits(&mut self, size_limits: SizeLimits) {
+        self.batch_handler.set_size_limits(size_limits);
+    }
+
+    /// Overrides the default number of accounts and contract codes kept in
+    /// the state cache shared across batches.
+    pub fn set_state_cache_sizes(&mut self, account_cache_size: usize, code_cache_size: usize) {
+        self.batch_handler
+            .set_state_cache_sizes(account_cache_size, code_cache_size);
+    }
+
+    /// Enables or disables storage prefetching (enabled by default).
+    #[cfg(feature = "prefetch")]
+    pub fn set_prefetch(&mut self, enabled: bool) {
+        self.prefetch = enabled;
+    }
+
+    /// Sets a hook which is invoked with the index of each transaction before
+    /// it is executed, e.g., to abort a batch half-way through.
+    #[cfg(all(test, feature = "test"))]
+    pub(crate) fn set_before_tx_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.before_tx_hook = Some(Box::new(hook));
+    }
+
+    #[cfg(test)]
+    fn before_tx(&self, index: usize) {
+        if let Some(ref hook) = self.before_tx_hook {
+            hook(index);
+        }
+    }
+
+    #[cfg(not(test))]
+    fn before_tx(&self, _index: usize) {}
+
+    /// Whether the current batch should be aborted.
+    fn is_aborted(&self) -> bool {
+        self.abort_batch
+            .as_ref()
+            .map(|b| b.load(Ordering::SeqCst))
+            .unwrap_or(false)
+    }
+
+    /// Storage prefixes to prefetch before executing `call`.
+    #[cfg(feature = "prefetch")]
+    fn prefetch_prefixes(&self, call: &DecodedCall) -> Vec<Prefix> {
+        let mut prefixes = Vec::new();
+
+        if let Action::Call(receiver) = (**call.transaction).action {
+            let mut account_code: Vec<u8> = receiver.to_vec();
+            account_code.extend_from_slice(MKVS_KEY_CODE);
+            prefixes.push(account_code.into());
+
+            let mut account_meta: Vec<u8> = receiver.to_vec();
+            account_meta.extend_from_slice(MKVS_KEY_METADATA);
+            prefixes.push(Prefix::from(account_meta));
+
+            // Storage slots read by the previous calls to the receiver.
+            prefixes.extend(
+                self.prefetch_hints
+                    .get(&receiver)
+                    .into_iter()
+                    .map(Prefix::from),
+            );
+        }
+
+        // Accounts and storage slots in the access list. Storage keys of
+        // confidential contracts are encrypted, so their slots can only be
+        // prefetched through the hints above.
+        for item in &call.access_list {
+            let mut account_code: Vec<u8> = item.address.to_vec();
+            account_code.extend_from_slice(MKVS_KEY_CODE);
+            prefixes.push(account_code.into());
+
+            let mut account_meta: Vec<u8> = item.address.to_vec();
+            account_meta.extend_from_slice(MKVS_KEY_METADATA);
+            prefixes.push(Prefix::from(account_meta));
+
+            for slot in &item.storage_keys {
+                let mut storage_key: Vec<u8> = item.address.to_vec();
+                storage_key.extend_from_slice(&slot[..]);
+                prefixes.push(Prefix::from(storage_key));
+            }
+        }
+
+        let mut account_meta: Vec<u8> = call.transaction.sender().to_vec();
+        account_meta.extend_from_slice(MKVS_KEY_METADATA);
+        prefixes.push(Prefix::from(account_meta));
+
+        prefixes
+    }
+
     fn decode_transaction(&self, call: &[u8], ctx: &mut Context) -> Result<DecodedCall> {
+        let args = self.decode_args(call)?;
+        self.check_transaction(args, ctx)
+    }
+
+    fn decode_args(&self, call: &[u8]) -> Result<TransactionArgs> {
         let call: TxnCall = cbor::from_slice(call).context("unable to parse call")?;
 
         if call.method != api::METHOD_TX {
@@ -75,12 +199,15 @@ impl Dispatcher {
             .into());
         }
 
-        let call_args: ByteBuf =
-            cbor::from_value(call.args).context("unable to parse call arguments")?;
-        let signed_transaction = check::tx(&call_args, ctx)?;
+        Ok(cbor::from_value(call.args).context("unable to parse call arguments")?)
+    }
+
+    fn check_transaction(&self, args: TransactionArgs, ctx: &mut Context) -> Result<DecodedCall> {
+        let signed_transaction = check::tx(args.raw(), ctx)?;
 
         Ok(DecodedCall {
             transaction: signed_transaction,
+            access_list: args.access_list().to_vec(),
         })
     }
 
@@ -112,13 +239,8 @@ impl TxnDispatcher for Dispatcher {
         let checked_calls: Vec<Result<DecodedCall>> = batch
             .iter()
             .map(|call| {
-                if self
-                    .abort_batch
-                    .as_ref()
-                    .map(|b| b.load(Ordering::SeqCst))
-                    .unwrap_or(false)
-                {
-                    bail!("batch aborted");
+                if self.is_aborted() {
+                    bail!(DispatchError::BatchAborted);
                 }
 
                 self.decode_transaction(call, &mut ctx)
@@ -159,36 +281,33 @@ impl TxnDispatcher for Dispatcher {
         #[cfg(feature = "prefetch")]
         let mut prefixes: Vec<Prefix> = Vec::new();
 
+        // Total size of the raw transactions accepted into this batch so far.
+        let max_batch_size = self.batch_handler.size_limits().max_batch_size;
+        let mut batch_size: usize = 0;
+
         // Decode and check transactions in this batch.
         let calls: Vec<Result<DecodedCall>> = batch
             .iter()
             .map(|call| {
-                if self
-                    .abort_batch
-                    .as_ref()
-                    .map(|b| b.load(Ordering::SeqCst))
-                    .unwrap_or(false)
-                {
-                    bail!("batch aborted");
+                if self.is_aborted() {
+                    bail!(DispatchError::BatchAborted);
                 }
-                let tx = self.decode_transaction(call, &mut ctx)?;
 
-                #[cfg(feature = "prefetch")]
-                {
-                    if let Action::Call(receiver) = (**tx.transaction).action {
-                        let mut account_code: Vec<u8> = receiver.to_vec();
-                        account_code.extend_from_slice(MKVS_KEY_CODE);
-                        prefixes.push(account_code.into());
-
-                        let mut account_meta: Vec<u8> = receiver.to_vec();
-                        account_meta.extend_from_slice(MKVS_KEY_METADATA);
-                        prefixes.push(Prefix::from(account_meta));
-                    }
+                let args = self.decode_args(call)?;
+                let size = args.raw().len();
+                let tx = self.check_transaction(args, &mut ctx)?;
 
-                    let mut account_meta: Vec<u8> = tx.transaction.sender().to_vec();
-                    account_meta.extend_from_slice(MKVS_KEY_METADATA);
-                    prefixes.push(Prefix::from(account_meta));
+                // Reject transactions which do not fit into the batch payload
+                // limit, the client should retry them in a later batch. The
+                // transaction checks already rejected any transaction which
+                // exceeds the limit on its own.
+                if batch_size + size > max_batch_size {
+                    return Err(TransactionError::BatchPayloadLimitReached.into());
                 }
+                batch_size += size;
+
+                #[cfg(feature = "prefetch")]
+                prefixes.extend(self.prefetch_prefixes(&tx));
 
                 Ok(tx)
             })
@@ -202,42 +321,77 @@ impl TxnDispatcher for Dispatcher {
             prefixes.sort_unstable();
             prefixes.dedup();
 
-            StorageContext::with_current(|mkvs, _untrusted_local| {
-                prefixes.drain_filter(|key| {
-                    mkvs.cache_contains_key(IoContext::create_child(&ctx.io_ctx), key)
+            if self.prefetch {
+                StorageContext::with_current(|mkvs, _untrusted_local| {
+                    prefixes.drain_filter(|key| {
+                        mkvs.cache_contains_key(IoContext::create_child(&ctx.io_ctx), key)
+                    });
+
+                    if prefixes.len() > 0 {
+                        mkvs.prefetch_prefixes(
+                            IoContext::create_child(&ctx.io_ctx),
+                            &prefixes,
+                            10_000, /* limit */
+                        )
+                    }
                 });
+            }
+        }
+
+        // Storage keys read by calls to each contract in this batch.
+        #[cfg(feature = "prefetch")]
+        let mut touched_keys: HashMap<Address, HashSet<Vec<u8>>> = HashMap::new();
+        #[cfg(feature = "prefetch")]
+        runtime_context!(ctx, BlockContext)
+            .storage_stats
+            .take_storage_keys();
+
+        // Process batch.
+        let mut outputs: Vec<(Vec<u8>, Tags)> = Vec::with_capacity(calls.len());
+        for (index, call) in calls.iter().enumerate() {
+            self.before_tx(index);
+            if self.is_aborted() {
+                break;
+            }
+
+            outputs.push(match call {
+                Ok(call) => self
+                    .encode_response(call, &mut ctx)
+                    .map(|r| (r, ctx.take_tags()))
+                    .unwrap_or_else(|err| (self.serialize_error(&err), Vec::new())),
+                Err(err) => (self.serialize_error(err), Vec::new()),
+            });
 
-                if prefixes.len() > 0 {
-                    mkvs.prefetch_prefixes(
-                        IoContext::create_child(&ctx.io_ctx),
-                        &prefixes,
-                        10_000, /* limit */
-                    )
+            #[cfg(feature = "prefetch")]
+            {
+                let keys = runtime_context!(ctx, BlockContext)
+                    .storage_stats
+                    .take_storage_keys();
+                if let Ok(call) = call {
+                    if let Action::Call(receiver) = (**call.transaction).action {
+                        touched_keys.entry(receiver).or_default().extend(keys);
+                    }
                 }
+            }
+        }
+
+        // Discard all state changes if the batch was aborted. The outputs of
+        // the executed calls are not returned as an `Ok` result would be
+        // committed as the outcome of the whole batch, so the abort is
+        // reported as an error which makes the host drop the batch.
+        if self.is_aborted() {
+            self.batch_handler.abort_batch(&mut ctx);
+            return Err(RuntimeError {
+                module: "".to_string(),
+                code: 1,
+                message: DispatchError::BatchAborted.to_string(),
             });
         }
 
-        // Process batch.
-        let outputs: Vec<(Vec<u8>, Tags)> = calls
-            .iter()
-            .map(|call| {
-                if self
-                    .abort_batch
-                    .as_ref()
-                    .map(|b| b.load(Ordering::SeqCst))
-                    .unwrap_or(false)
-                {
-                    return (self.serialize_error(&anyhow!("batch aborted")), Vec::new());
-                }
-                match call {
-                    Ok(call) => self
-                        .encode_response(call, &mut ctx)
-                        .map(|r| (r, ctx.take_tags()))
-                        .unwrap_or_else(|err| (self.serialize_error(&err), Vec::new())),
-                    Err(err) => (self.serialize_error(err), Vec::new()),
-                }
-            })
-            .collect();
+        #[cfg(feature = "prefetch")]
+        for (contract, keys) in touched_keys {
+            self.prefetch_hints.update(contract, keys);
+        }
 
         // Invoke end batch handler.
         self.batch_handler.end_batch(&mut ctx);
@@ -254,10 +408,106 @@ impl TxnDispatcher for Dispatcher {
         })
     }
 
-    fn finalize(&self, _new_storage_root: Hash) {}
+    fn finalize(&self, new_storage_root: Hash) {
+        self.batch_handler.finalize(new_storage_root);
+    }
 
     /// Configure abort batch flag.
     fn set_abort_batch_flag(&mut self, abort_batch: Arc<AtomicBool>) {
         self.abort_batch = Some(abort_batch);
     }
 }
+
+#[cfg(all(test, feature = "test", feature = "prefetch"))]
+mod tests {
+    use ethcore::transaction::Transaction;
+    use ethereum_types::{H256, U256};
+
+    use super::*;
+    use crate::test;
+
+    /// Deploys a contract which reads storage slot 0 whenever it is called.
+    fn deploy_slot_reader(client: &mut test::Client) -> Address {
+        // The constructor stores 1 into slot 0 and returns the runtime code
+        // PUSH1 0, SLOAD, POP, STOP.
+        let initcode = hex::decode("60016000556460005450006000526005601bf3").unwrap();
+        client.create_contract(initcode, &U256::zero()).1
+    }
+
+    #[test]
+    fn test_prefetch_hints() {
+        let mut client = test::Client::new();
+        let dispatcher = Dispatcher::new(client.km_client.clone());
+        let contract = deploy_slot_reader(&mut client);
+
+        let nonce = client.nonce(&client.keypair.address());
+        let call = client.tx_call(Some(&contract), vec![], &U256::zero(), nonce);
+        let result = client
+            .dispatch_batch(&dispatcher, vec![call])
+            .expect("batch should succeed");
+        let output =
+            test::Client::decode_output(&result.results[0].output).expect("call should succeed");
+        assert_eq!(output.status_code, 1);
+
+        // The storage read by the call is remembered for the contract.
+        let hints = dispatcher.prefetch_hints.get(&contract);
+        assert!(!hints.is_empty());
+        assert!(hints.iter().all(|key| key.starts_with(&contract[..])));
+
+        // The next call to the contract prefetches it.
+        let next = DecodedCall {
+            transaction: Transaction {
+                action: Action::Call(contract),
+                nonce: nonce + U256::one(),
+                gas_price: client.gas_price,
+                gas: client.gas_limit,
+                value: U256::zero(),
+                data: vec![],
+            }
+            .fake_sign(client.keypair.address()),
+            access_list: vec![],
+        };
+        let prefixes = dispatcher.prefetch_prefixes(&next);
+        for key in hints {
+            assert!(prefixes.contains(&Prefix::from(key)));
+        }
+    }
+
+    #[test]
+    fn test_prefetch_access_list() {
+        let mut client = test::Client::new();
+        let dispatcher = Dispatcher::new(client.km_client.clone());
+        let contract = deploy_slot_reader(&mut client);
+
+        // The key of a plain storage slot is the slot following the address.
+        let mut slot_key = contract.to_vec();
+        slot_key.extend_from_slice(&H256::zero()[..]);
+
+        let nonce = client.nonce(&client.keypair.address());
+        let call = client.tx_call(Some(&contract), vec![], &U256::zero(), nonce);
+        client
+            .dispatch_batch(&dispatcher, vec![call])
+            .expect("batch should succeed");
+        assert!(dispatcher.prefetch_hints.get(&contract).contains(&slot_key));
+
+        // A dispatcher without hints prefetches the slot named in the access list.
+        let dispatcher = Dispatcher::new(client.km_client.clone());
+        let call = client.tx_call_with_access_list(
+            Some(&contract),
+            vec![],
+            &U256::zero(),
+            nonce + U256::one(),
+            vec![AccessListItem {
+                address: contract,
+                storage_keys: vec![H256::zero()],
+            }],
+        );
+        let call = client
+            .execute_batch(|_client, ctx| dispatcher.decode_transaction(&call, ctx))
+            .expect("call should decode");
+        assert_eq!(call.access_list.len(), 1);
+        assert!(dispatcher
+            .prefetch_prefixes(&call)
+            .contains(&Prefix::from(slot_key)));
+    }
+}
@@ -1,10 +1,9 @@
 use byteorder::{BigEndian, ReadBytesExt};
 use ethcore::{
     self,
-    state::{CleanupMode, State},
+    state::{backend::Backend, CleanupMode, State},
 };
 use ethereum_types::{Address, H256, U256};
-use oasis_ethwasi_runtime_common::parity::NullBackend;
 use std::str::FromStr;
 
 pub struct FundManager;
@@ -27,7 +26,7 @@ impl FundManager {
     const TOTAL_AMOUNT: &'static str = "115EEC47F6CF7E35000000";
 
     /// Primary unlock token method
-    pub fn try_unlock(timestamp: i64, state: &mut State<NullBackend>) -> U256 {
+    pub fn try_unlock<B: Backend>(timestamp: i64, state: &mut State<B>) -> U256 {
         // +---------------+-------------------+--------------------+-------------------+
         // | Storage field |       [0:16)      |       [16:24)      |      [24:32)      |
         // +---------------+-------------------+--------------------+-------------------+
@@ -1,17 +1,15 @@
 //! Oasis runtime.
+#![cfg_attr(feature = "prefetch", feature(drain_filter))]
 extern crate byteorder;
 #[cfg(feature = "test")]
-extern crate elastic_array;
-#[cfg(feature = "test")]
 extern crate ethkey;
-#[cfg(feature = "test")]
-#[macro_use]
-extern crate serde_json;
 
 pub mod block;
 pub mod dispatcher;
 mod fund;
 pub mod methods;
+#[cfg(feature = "prefetch")]
+mod prefetch;
 
 #[cfg(feature = "test")]
 pub mod test;
@@ -1,10 +1,6 @@
 //! Methods exported to Oasis Core clients.
 use anyhow::Result;
-use ethcore::{
-    rlp,
-    transaction::{SignedTransaction, UnverifiedTransaction},
-    types::receipt::TransactionOutcome,
-};
+use ethcore::{transaction::SignedTransaction, types::receipt::TransactionOutcome};
 use ethereum_types::U256;
 use oasis_core_runtime::{
     runtime_context,
@@ -13,9 +9,9 @@ use oasis_core_runtime::{
 use oasis_ethwasi_runtime_api::{ExecutionResult, LogEntry, TransactionError};
 #[cfg_attr(feature = "test", allow(unused))]
 use oasis_ethwasi_runtime_common::{
-    genesis, BLOCK_GAS_LIMIT, MIN_GAS_PRICE_GWEI, TAG_ETH_LOG_ADDRESS, TAG_ETH_LOG_TOPICS,
-    TAG_ETH_TX_HASH,
+    genesis, BLOCK_GAS_LIMIT, TAG_ETH_LOG_ADDRESS, TAG_ETH_LOG_TOPICS, TAG_ETH_TX_HASH,
 };
+use slog::debug;
 
 use crate::block::BlockContext;
 
@@ -24,29 +20,10 @@ pub mod check {
     use super::*;
 
     /// Check transaction.
-    pub fn tx(txn: &[u8], _ctx: &mut TxnContext) -> Result<SignedTransaction> {
-        let decoded: UnverifiedTransaction = rlp::decode(txn)?;
-
-        // Check that gas < block gas limit.
-        if decoded.as_unsigned().gas > BLOCK_GAS_LIMIT.into() {
-            return Err(TransactionError::TooMuchGas.into());
-        }
+    pub fn tx(txn: &[u8], ctx: &mut TxnContext) -> Result<SignedTransaction> {
+        let limits = runtime_context!(ctx, BlockContext).size_limits;
 
-        // Check signature.
-        let signed = SignedTransaction::new(decoded)?;
-
-        // Check gas price.
-        if signed.gas_price < MIN_GAS_PRICE_GWEI.into() {
-            return Err(TransactionError::GasPrice.into());
-        }
-
-        // Verify chain id
-        match signed.verify_basic(true, Some(genesis::SPEC.engine.machine().params().chain_id), false) {
-            Ok(_) => {},
-            Err(_) => return Err(TransactionError::InvalidChainId.into()),
-        }
-
-        Ok(signed)
+        oasis_ethwasi_runtime_common::check::tx(txn, &limits)
     }
 }
 
@@ -80,6 +57,7 @@ pub mod execute {
         }
 
         // Create Ethereum state instance and apply the transaction.
+        let storage_before = ectx.storage_stats.snapshot();
         let outcome = ectx
             .state
             .apply(
@@ -96,6 +74,21 @@ pub mod execute {
         // Add to set of executed transactions.
         ectx.transaction_set.insert(txn_hash);
 
+        // Most writes are only flushed when the batch is committed, so they
+        // are accounted to the batch rather than the transaction. The counters
+        // depend on the local cache, so they are only logged and never become
+        // part of the (committed) transaction output.
+        let storage_stats = ectx.storage_stats.snapshot().since(&storage_before);
+        let storage_total = storage_stats.total();
+        debug!(ectx.logger, "Transaction storage I/O";
+            "tx_hash" => ?txn_hash,
+            "reads" => storage_total.reads,
+            "read_bytes" => storage_total.read_bytes,
+            "code_reads" => storage_stats.code.reads,
+            "code_read_bytes" => storage_stats.code.read_bytes,
+            "slot_reads" => storage_stats.storage.reads
+        );
+
         // Calculate the amount of gas used by this transaction and update the
         // cumulative gas used for the batch. Note: receipt.gas_used is the cumulative
         // gas used after executing the given transaction.
@@ -0,0 +1,47 @@
+//! Storage prefetch hints.
+use std::{collections::HashSet, sync::Mutex};
+
+use ethereum_types::Address;
+use lru_cache::LruCache;
+
+/// Number of contracts for which hints are kept.
+const HINT_CACHE_SIZE: usize = 1024;
+/// Maximum number of storage keys remembered per contract.
+const MAX_KEYS_PER_CONTRACT: usize = 256;
+
+/// Storage keys read by the previous executions of each contract.
+///
+/// The hints are only used to warm up the storage cache before a batch is
+/// executed, so stale hints cost a wasted fetch but never affect results.
+pub struct PrefetchHints(Mutex<LruCache<Address, Vec<Vec<u8>>>>);
+
+impl PrefetchHints {
+    pub fn new() -> Self {
+        PrefetchHints(Mutex::new(LruCache::new(HINT_CACHE_SIZE)))
+    }
+
+    /// Returns the storage keys read the last time `contract` was called.
+    pub fn get(&self, contract: &Address) -> Vec<Vec<u8>> {
+        self.0
+            .lock()
+            .unwrap()
+            .get_mut(contract)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replaces the hints for `contract` with the keys read while executing
+    /// calls to it.
+    pub fn update(&self, contract: Address, keys: HashSet<Vec<u8>>) {
+        if keys.is_empty() {
+            // Keys read by earlier transactions in the same batch are served
+            // from the state cache, so keep the previous hints.
+            return;
+        }
+
+        let mut keys: Vec<Vec<u8>> = keys.into_iter().collect();
+        keys.sort_unstable();
+        keys.truncate(MAX_KEYS_PER_CONTRACT);
+        self.0.lock().unwrap().insert(contract, keys);
+    }
+}
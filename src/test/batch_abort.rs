@@ -0,0 +1,81 @@
+//! Tests for aborting batches.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use ethereum_types::U256;
+use oasis_core_runtime::transaction::dispatcher::Dispatcher as TxnDispatcher;
+
+use super::Client;
+use crate::dispatcher::Dispatcher;
+
+/// Encodes `count` empty deploys from the client's account, starting at `nonce`.
+fn deploys(client: &Client, nonce: U256, count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| client.tx_call(None, vec![], &U256::zero(), nonce + U256::from(i)))
+        .collect()
+}
+
+#[test]
+fn test_abort_mid_batch() {
+    let mut client = Client::new();
+    let mut dispatcher = Dispatcher::new(client.km_client.clone());
+
+    // Abort right before the third transaction is executed (only once).
+    let abort = Arc::new(AtomicBool::new(false));
+    dispatcher.set_abort_batch_flag(abort.clone());
+    let armed = Arc::new(AtomicBool::new(true));
+    let (hook_abort, hook_armed) = (abort.clone(), armed.clone());
+    dispatcher.set_before_tx_hook(move |index| {
+        if index == 2 && hook_armed.swap(false, Ordering::SeqCst) {
+            hook_abort.store(true, Ordering::SeqCst);
+        }
+    });
+
+    let address = client.keypair.address();
+    let nonce = client.nonce(&address);
+    let balance = client.balance(&address);
+    let state_root = client.header.state_root;
+
+    let calls = deploys(&client, nonce, 4);
+    let err = client
+        .dispatch_batch(&dispatcher, calls.clone())
+        .err()
+        .expect("batch should be aborted");
+    assert_eq!(err.message, "batch aborted");
+
+    // Nothing from the aborted batch may have been committed.
+    assert_eq!(client.header.state_root, state_root);
+    assert_eq!(client.nonce(&address), nonce);
+    assert_eq!(client.balance(&address), balance);
+
+    // The same transactions are valid again once the flag is cleared, so the
+    // aborted nonce updates did not leak into any cached state either.
+    abort.store(false, Ordering::SeqCst);
+    let result = client
+        .dispatch_batch(&dispatcher, calls)
+        .expect("batch should succeed");
+    assert_eq!(result.results.len(), 4);
+    for output in result.results {
+        let result = Client::decode_output(&output.output).expect("call should succeed");
+        assert_eq!(result.status_code, 1);
+    }
+    assert_eq!(client.nonce(&address), nonce + U256::from(4));
+}
+
+#[test]
+fn test_abort_before_batch() {
+    let mut client = Client::new();
+    let mut dispatcher = Dispatcher::new(client.km_client.clone());
+    dispatcher.set_abort_batch_flag(Arc::new(AtomicBool::new(true)));
+
+    let address = client.keypair.address();
+    let nonce = client.nonce(&address);
+    let state_root = client.header.state_root;
+
+    let calls = deploys(&client, nonce, 2);
+    assert!(client.dispatch_batch(&dispatcher, calls).is_err());
+    assert_eq!(client.header.state_root, state_root);
+    assert_eq!(client.nonce(&address), nonce);
+}
@@ -1,42 +1,49 @@
 //! Test client to interact with an oasis-ethwasi-runtime blockchain.
 use std::{collections::HashMap, str::FromStr, sync::Arc};
 
-use byteorder::{BigEndian, ByteOrder};
-use elastic_array::ElasticArray128;
 use ethcore::{
     executive::contract_address,
     rlp,
     transaction::{Action, Transaction as EthcoreTransaction},
-    vm::{ConfidentialCtx as EthConfidentialCtx, OASIS_HEADER_PREFIX},
+    vm::ConfidentialCtx as EthConfidentialCtx,
 };
 use ethereum_types::{Address, H256, U256};
 use ethkey::{KeyPair as EtyKeyPair, Secret};
 use oasis_core_keymanager_client::{self, KeyManagerClient, KeyPair, KeyPairId};
 use oasis_core_runtime::{
-    common::crypto::{
-        hash::Hash,
-        mrae::nonce::{Nonce, NONCE_SIZE},
+    common::{
+        cbor,
+        crypto::{
+            hash::Hash,
+            mrae::nonce::{Nonce, NONCE_SIZE},
+        },
     },
     consensus::roothash::Header,
     executor::Executor,
     runtime_context,
     storage::{
         mkvs::{sync::NoopReadSyncer, OverlayTree, RootType, Tree},
-        StorageContext,
+        KeyValue, StorageContext,
     },
-    transaction::Context as TxnContext,
+    transaction::{
+        dispatcher::{Dispatcher as TxnDispatcher, ExecuteBatchResult},
+        types::{TxnBatch, TxnCall, TxnOutput},
+        Context as TxnContext,
+    },
+    types::Error as RuntimeError,
 };
 
 use io_context::Context as IoContext;
 use keccak_hash::keccak;
-use oasis_ethwasi_runtime_api::ExecutionResult;
+use oasis_ethwasi_runtime_api::{AccessListItem, ExecutionResult, TransactionArgs, METHOD_TX};
 use oasis_ethwasi_runtime_common::{
     confidential::ConfidentialCtx,
     genesis,
+    header::OasisHeader,
     parity::NullBackend,
     storage::{MemoryKeyValue, ThreadLocalMKVS},
 };
-use serde_json::map::Map;
+use serde_bytes::ByteBuf;
 
 use crate::{
     block::{BlockContext, OasisBatchHandler},
@@ -59,6 +66,8 @@ pub struct Client {
     pub header: Header,
     /// In-memory MKVS.
     pub mkvs: Option<Tree>,
+    /// Untrusted local storage.
+    pub untrusted_local: Arc<dyn KeyValue>,
     /// Key manager client.
     pub km_client: Arc<dyn KeyManagerClient>,
     /// Results.
@@ -67,6 +76,12 @@ pub struct Client {
 
 impl Client {
     pub fn new() -> Self {
+        Self::with_untrusted_local(Arc::new(MemoryKeyValue::new()))
+    }
+
+    /// Creates a client which uses the given untrusted local storage, e.g., a
+    /// `FileKeyValue` that should persist across test runs.
+    pub fn with_untrusted_local(untrusted_local: Arc<dyn KeyValue>) -> Self {
         let km_client = Arc::new(oasis_core_keymanager_client::mock::MockClient::new());
         let mut mkvs = Tree::make()
             .with_root_type(RootType::State)
@@ -74,8 +89,7 @@ impl Client {
         let mut overlay = OverlayTree::new(&mut mkvs);
 
         // Initialize genesis.
-        let untrusted_local = Arc::new(MemoryKeyValue::new());
-        StorageContext::enter(&mut overlay, untrusted_local, || {
+        StorageContext::enter(&mut overlay, untrusted_local.clone(), || {
             genesis::SPEC
                 .ensure_db_good(
                     Box::new(ThreadLocalMKVS::new(IoContext::background())),
@@ -102,6 +116,7 @@ impl Client {
             gas_price: U256::from(1000000000),
             gas_limit: U256::from(1000000),
             mkvs: Some(mkvs),
+            untrusted_local,
             km_client,
             header: Header {
                 round: 0,
@@ -124,7 +139,7 @@ impl Client {
         let results = Default::default();
         let mut ctx = TxnContext::new(IoContext::background().freeze(), &header, &results, 0, true);
         let handler = OasisBatchHandler::new(self.km_client.clone());
-        let untrusted_local = Arc::new(MemoryKeyValue::new());
+        let untrusted_local = self.untrusted_local.clone();
 
         let result = StorageContext::enter(&mut overlay, untrusted_local, || {
             handler.start_batch(&mut ctx);
@@ -154,7 +169,7 @@ impl Client {
             false,
         );
         let handler = OasisBatchHandler::new(self.km_client.clone());
-        let untrusted_local = Arc::new(MemoryKeyValue::new());
+        let untrusted_local = self.untrusted_local.clone();
 
         let result = StorageContext::enter(&mut overlay, untrusted_local, || {
             handler.start_batch(&mut ctx);
@@ -180,6 +195,117 @@ impl Client {
         result
     }
 
+    /// Executes a batch of runtime calls (see `tx_call`) through the given
+    /// dispatcher, like the Oasis Core worker would. State changes are only
+    /// committed if the dispatcher succeeds.
+    pub fn dispatch_batch(
+        &mut self,
+        dispatcher: &dispatcher::Dispatcher,
+        calls: Vec<Vec<u8>>,
+    ) -> Result<ExecuteBatchResult, RuntimeError> {
+        let mut mkvs = self.mkvs.take().expect("nested execute_batch not allowed");
+        let mut overlay = OverlayTree::new(&mut mkvs);
+        let header = self.header.clone();
+        let results = Default::default();
+        let ctx = TxnContext::new(
+            IoContext::background().freeze(),
+            &header,
+            &results,
+            0,
+            false,
+        );
+        let batch = TxnBatch::new(calls);
+
+        let result = StorageContext::enter(&mut overlay, self.untrusted_local.clone(), || {
+            dispatcher.execute_batch(ctx, &batch)
+        });
+
+        if result.is_ok() {
+            let (_, new_state_root) = overlay
+                .commit_both(
+                    IoContext::background(),
+                    Default::default(),
+                    self.header.round + 1,
+                )
+                .expect("mkvs commit must succeed");
+            dispatcher.finalize(new_state_root);
+            self.header.state_root = new_state_root;
+            self.header.previous_hash = Hash::digest_bytes(self.header.previous_hash.as_ref());
+            self.header.round += 1;
+        }
+        self.mkvs = Some(mkvs);
+
+        result
+    }
+
+    /// Signs a transaction and encodes it as a runtime call for `dispatch_batch`.
+    pub fn tx_call(
+        &self,
+        contract: Option<&Address>,
+        data: Vec<u8>,
+        value: &U256,
+        nonce: U256,
+    ) -> Vec<u8> {
+        let raw = self.raw_tx(contract, data, value, nonce);
+        Self::encode_tx_call(TransactionArgs::Raw(ByteBuf::from(raw)))
+    }
+
+    /// Signs a transaction and encodes it together with an access list as a
+    /// runtime call for `dispatch_batch`.
+    pub fn tx_call_with_access_list(
+        &self,
+        contract: Option<&Address>,
+        data: Vec<u8>,
+        value: &U256,
+        nonce: U256,
+        access_list: Vec<AccessListItem>,
+    ) -> Vec<u8> {
+        let transaction = self.raw_tx(contract, data, value, nonce);
+        Self::encode_tx_call(TransactionArgs::WithAccessList {
+            transaction,
+            access_list,
+        })
+    }
+
+    /// Signs a transaction and returns its raw encoding.
+    pub fn raw_tx(
+        &self,
+        contract: Option<&Address>,
+        data: Vec<u8>,
+        value: &U256,
+        nonce: U256,
+    ) -> Vec<u8> {
+        let tx = EthcoreTransaction {
+            action: match contract {
+                Some(contract) => Action::Call(*contract),
+                None => Action::Create,
+            },
+            nonce,
+            gas_price: self.gas_price,
+            gas: self.gas_limit,
+            value: *value,
+            data,
+        }
+        .sign(&self.keypair.secret(), None);
+
+        rlp::encode(&tx).into_vec()
+    }
+
+    fn encode_tx_call(args: TransactionArgs) -> Vec<u8> {
+        cbor::to_vec(&TxnCall {
+            method: METHOD_TX.to_owned(),
+            args: cbor::to_value(args),
+        })
+    }
+
+    /// Decodes the output of a dispatched call.
+    pub fn decode_output(output: &[u8]) -> Result<ExecutionResult, String> {
+        match cbor::from_slice(output).map_err(|err| err.to_string())? {
+            TxnOutput::Success(value) => cbor::from_value(value).map_err(|err| err.to_string()),
+            TxnOutput::Error(err) => Err(err),
+        }
+    }
+
     /// Sets the timestamp passed to the runtime.
     pub fn set_timestamp(&mut self, timestamp: u64) {
         self.header.timestamp = timestamp;
@@ -190,9 +316,10 @@ impl Client {
     pub fn confidential_data(&self, contract: Option<&Address>, data: Vec<u8>) -> Vec<u8> {
         if contract.is_none() {
             // Don't encrypt confidential deploys.
-            let mut conf_deploy_data = Self::make_header(None, Some(true));
-            conf_deploy_data.append(&mut data.clone());
-            return conf_deploy_data;
+            return OasisHeader::builder()
+                .confidential(true)
+                .build()
+                .prepend_to(&data);
         }
 
         let contract_addr = contract.unwrap();
@@ -213,17 +340,15 @@ impl Client {
         (hash, address.unwrap())
     }
 
-    /// Creates a contract with specified expiry and confidentiality, returns the
+    /// Creates a contract with the specified Oasis header, returns the
     /// transaction hash for the deploy and the address of the contract.
     pub fn create_contract_with_header(
         &mut self,
         code: Vec<u8>,
         balance: &U256,
-        expiry: Option<u64>,
-        confidentiality: Option<bool>,
+        header: OasisHeader,
     ) -> (H256, Address) {
-        let mut data = Self::make_header(expiry, confidentiality);
-        data.extend(code);
+        let data = header.prepend_to(&code);
         let (hash, address) = self
             .send(None, data, balance, None)
             .expect("deployment should succeed");
@@ -298,6 +423,7 @@ impl Client {
             let raw = rlp::encode(&tx);
             let decoded_call = dispatcher::DecodedCall {
                 transaction: methods::check::tx(&raw, ctx).map_err(|err| err.to_string())?,
+                access_list: vec![],
             };
             let result = methods::execute::tx(&decoded_call, ctx).map_err(|err| err.to_string())?;
             client.results.insert(tx.hash(), result);
@@ -436,32 +562,4 @@ impl Client {
         })
         .unwrap()
     }
-
-    /// Returns a valid contract deployment header with specified expiry and confidentiality.
-    fn make_header(expiry: Option<u64>, confidential: Option<bool>) -> Vec<u8> {
-        // start with header prefix
-        let mut data = ElasticArray128::from_slice(&OASIS_HEADER_PREFIX[..]);
-
-        // header version 1
-        let mut version = [0u8; 2];
-        BigEndian::write_u16(&mut version, 1 as u16);
-
-        // contents (JSON)
-        let mut map = Map::new();
-        confidential
-            .map(|confidential| map.insert("confidential".to_string(), confidential.into()));
-        expiry.map(|expiry| map.insert("expiry".to_string(), expiry.into()));
-        let contents = json!(map).to_string().into_bytes();
-
-        // contents length
-        let mut length = [0u8; 2];
-        BigEndian::write_u16(&mut length, contents.len() as u16);
-
-        // append header version, length and contents
-        data.append_slice(&version);
-        data.append_slice(&length);
-        data.append_slice(&contents);
-
-        data.into_vec()
-    }
 }
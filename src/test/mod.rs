@@ -1,5 +1,7 @@
 //! Module for common utilities used in runtime tests.
 
+#[cfg(test)]
+mod batch_abort;
 pub mod client;
 
 // Re-exports.
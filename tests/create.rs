@@ -144,3 +144,19 @@ fn create_and_set_storage() {
     assert_eq!(retrieve_a, U256::from(33));
     assert_eq!(retrieve_b, U256::from(55));
 }
+
+/// Rejects deploys of wasm modules which do not follow the ewasm contract
+/// interface before they are executed.
+#[test]
+fn create_invalid_wasm() {
+    let mut client = test::Client::new();
+    let nonce = client.nonce(&client.keypair.address());
+
+    // Valid magic and version, but no exports.
+    let code = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    let err = client
+        .send(None, code, &U256::zero(), None)
+        .expect_err("deploy should be rejected");
+    assert!(err.starts_with("invalid ewasm module"));
+    assert_eq!(client.nonce(&client.keypair.address()), nonce);
+}
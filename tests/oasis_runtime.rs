@@ -154,6 +154,7 @@ fn test_signature_verification() {
     let check_should_fail = client.check_batch(|_client, ctx| {
         let call = dispatcher::DecodedCall {
             transaction: methods::check::tx(&rlp::encode(&bad_sig).into_vec(), ctx)?,
+            access_list: vec![],
         };
         methods::execute::tx(&call, ctx)
     });
@@ -169,6 +170,7 @@ fn test_signature_verification() {
     let check_should_pass = client.check_batch(|_client, ctx| {
         let call = dispatcher::DecodedCall {
             transaction: methods::check::tx(&rlp::encode(&good_sig).into_vec(), ctx)?,
+            access_list: vec![],
         };
         methods::execute::tx(&call, ctx)
     });
@@ -0,0 +1,203 @@
+//! Storage round trips and batch latency with and without storage prefetching.
+//!
+//! Batches are executed on a tree which fetches all nodes from a remote tree
+//! through a read syncer with a fixed latency per round trip, like a compute
+//! node fetching state from the storage nodes.
+//!
+//! Run the benchmark with
+//! `cargo test --features test,prefetch --test prefetch -- --ignored`.
+#![cfg(feature = "prefetch")]
+
+extern crate anyhow;
+extern crate ethereum_types;
+extern crate io_context;
+extern crate oasis_core_runtime;
+extern crate oasis_ethwasi_runtime;
+
+mod contracts;
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use ethereum_types::{Address, U256};
+use io_context::Context as IoContext;
+use oasis_core_runtime::{
+    storage::{
+        mkvs::{
+            sync::{GetPrefixesRequest, GetRequest, IterateRequest, ProofResponse, ReadSync},
+            OverlayTree, Root, RootType, Tree,
+        },
+        StorageContext,
+    },
+    transaction::{
+        dispatcher::Dispatcher as TxnDispatcher, types::TxnBatch, Context as TxnContext,
+    },
+};
+use oasis_ethwasi_runtime::{dispatcher::Dispatcher, test};
+
+/// Number of batches executed per run.
+const BATCHES: usize = 5;
+/// Number of transactions per batch.
+const BATCH_SIZE: usize = 20;
+/// Latency of a storage round trip in the benchmark.
+const LATENCY: Duration = Duration::from_millis(5);
+
+/// Read syncer which serves the nodes of a remote tree and waits for the
+/// configured latency on every round trip.
+struct LatencyReadSyncer {
+    remote: Rc<RefCell<Tree>>,
+    latency: Duration,
+    round_trips: Rc<Cell<usize>>,
+}
+
+impl LatencyReadSyncer {
+    fn round_trip(&self) {
+        self.round_trips.set(self.round_trips.get() + 1);
+        thread::sleep(self.latency);
+    }
+}
+
+impl ReadSync for LatencyReadSyncer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, ctx: IoContext, request: GetRequest) -> Result<ProofResponse> {
+        self.round_trip();
+        self.remote.borrow_mut().sync_get(ctx, request)
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        ctx: IoContext,
+        request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        self.round_trip();
+        self.remote.borrow_mut().sync_get_prefixes(ctx, request)
+    }
+
+    fn sync_iterate(&mut self, ctx: IoContext, request: IterateRequest) -> Result<ProofResponse> {
+        self.round_trip();
+        self.remote.borrow_mut().sync_iterate(ctx, request)
+    }
+}
+
+/// Average cost of executing a batch.
+struct Measurement {
+    latency: Duration,
+    round_trips: usize,
+}
+
+/// Encodes `BATCH_SIZE` increments of the counter, starting at `nonce`.
+fn increments(client: &test::Client, contract: &Address, nonce: U256) -> Vec<Vec<u8>> {
+    (0..BATCH_SIZE)
+        .map(|i| {
+            client.tx_call(
+                Some(contract),
+                contracts::counter::increment_counter_sighash(),
+                &U256::zero(),
+                nonce + U256::from(i),
+            )
+        })
+        .collect()
+}
+
+/// Deploys a counter contract, calls it once so that the dispatcher learns
+/// its prefetch hints, and then executes `BATCHES` batches of increments,
+/// each on a tree which starts out with no nodes cached.
+fn run(prefetch: bool, latency: Duration) -> Measurement {
+    let mut client = test::Client::new();
+    let mut dispatcher = Dispatcher::new(client.km_client.clone());
+    dispatcher.set_prefetch(prefetch);
+    // Accounts and code must be read from storage in every batch.
+    dispatcher.set_state_cache_sizes(0, 0);
+
+    let (_, contract): (_, Address) =
+        client.create_contract(contracts::counter::solidity_initcode(), &U256::zero());
+    let nonce = client.nonce(&client.keypair.address());
+    client
+        .dispatch_batch(&dispatcher, increments(&client, &contract, nonce))
+        .expect("batch should succeed");
+    let calls = increments(&client, &contract, nonce + U256::from(BATCH_SIZE));
+
+    let remote = Rc::new(RefCell::new(client.mkvs.take().expect("tree should exist")));
+    let round_trips = Rc::new(Cell::new(0));
+    let mut elapsed = Duration::default();
+    for _ in 0..BATCHES {
+        // The batches are not committed, so they all start from the same state.
+        let mut tree = Tree::make()
+            .with_root(Root {
+                version: client.header.round,
+                root_type: RootType::State,
+                hash: client.header.state_root,
+                ..Default::default()
+            })
+            .new(Box::new(LatencyReadSyncer {
+                remote: remote.clone(),
+                latency,
+                round_trips: round_trips.clone(),
+            }));
+        let mut overlay = OverlayTree::new(&mut tree);
+        let header = client.header.clone();
+        let results = Default::default();
+        let ctx = TxnContext::new(
+            IoContext::background().freeze(),
+            &header,
+            &results,
+            0,
+            false,
+        );
+        let batch = TxnBatch::new(calls.clone());
+
+        let start = Instant::now();
+        let result = StorageContext::enter(&mut overlay, client.untrusted_local.clone(), || {
+            dispatcher.execute_batch(ctx, &batch)
+        })
+        .expect("batch should succeed");
+        elapsed += start.elapsed();
+
+        for output in result.results {
+            let result = test::Client::decode_output(&output.output).expect("call should succeed");
+            assert_eq!(result.status_code, 1);
+        }
+    }
+
+    Measurement {
+        latency: elapsed / BATCHES as u32,
+        round_trips: round_trips.get() / BATCHES,
+    }
+}
+
+#[test]
+fn test_prefetch_round_trips() {
+    let without = run(false, Duration::default());
+    let with = run(true, Duration::default());
+
+    assert!(
+        with.round_trips < without.round_trips,
+        "round trips per batch: without prefetch {}, with prefetch {}",
+        without.round_trips,
+        with.round_trips,
+    );
+}
+
+#[test]
+#[ignore]
+fn bench_prefetch_batch_latency() {
+    let without = run(false, LATENCY);
+    let with = run(true, LATENCY);
+
+    assert!(
+        with.latency < without.latency,
+        "average batch latency ({} txs): without prefetch {:?}, with prefetch {:?}",
+        BATCH_SIZE,
+        without.latency,
+        with.latency,
+    );
+}
@@ -0,0 +1,137 @@
+extern crate ethereum_types;
+extern crate oasis_ethwasi_runtime;
+extern crate oasis_ethwasi_runtime_common;
+
+use ethereum_types::U256;
+use oasis_ethwasi_runtime::{dispatcher::Dispatcher, test};
+use oasis_ethwasi_runtime_common::SizeLimits;
+
+/// Dispatches `calls` in a single batch and returns the output of each call.
+fn dispatch(
+    client: &mut test::Client,
+    dispatcher: &Dispatcher,
+    calls: Vec<Vec<u8>>,
+) -> Vec<Result<(), String>> {
+    client
+        .dispatch_batch(dispatcher, calls)
+        .expect("batch should succeed")
+        .results
+        .into_iter()
+        .map(|output| test::Client::decode_output(&output.output).map(|_| ()))
+        .collect()
+}
+
+#[test]
+fn test_tx_too_large() {
+    let mut client = test::Client::new();
+    let mut dispatcher = Dispatcher::new(client.km_client.clone());
+    dispatcher.set_size_limits(SizeLimits {
+        max_tx_size: 1024,
+        ..Default::default()
+    });
+
+    let contract = client.keypair.address();
+    let nonce = client.nonce(&contract);
+    let calls = vec![
+        client.tx_call(Some(&contract), vec![0; 2048], &U256::zero(), nonce),
+        client.tx_call(Some(&contract), vec![0; 16], &U256::zero(), nonce),
+    ];
+
+    let results = dispatch(&mut client, &dispatcher, calls);
+    assert!(results[0]
+        .as_ref()
+        .unwrap_err()
+        .starts_with("transaction too large"));
+    assert!(results[1].is_ok());
+}
+
+#[test]
+fn test_init_code_too_large() {
+    let mut client = test::Client::new();
+    let mut dispatcher = Dispatcher::new(client.km_client.clone());
+    dispatcher.set_size_limits(SizeLimits {
+        max_init_code_size: 1024,
+        ..Default::default()
+    });
+
+    let contract = client.keypair.address();
+    let nonce = client.nonce(&contract);
+    let calls = vec![
+        client.tx_call(None, vec![0; 2048], &U256::zero(), nonce),
+        // Calls are not limited by the init code size.
+        client.tx_call(Some(&contract), vec![0; 2048], &U256::zero(), nonce),
+    ];
+
+    let results = dispatch(&mut client, &dispatcher, calls);
+    assert!(results[0]
+        .as_ref()
+        .unwrap_err()
+        .starts_with("contract init code too large"));
+    assert!(results[1].is_ok());
+}
+
+#[test]
+fn test_batch_payload_limit() {
+    let mut client = test::Client::new();
+    let mut dispatcher = Dispatcher::new(client.km_client.clone());
+
+    let contract = client.keypair.address();
+    let nonce = client.nonce(&contract);
+    let calls: Vec<Vec<u8>> = (0..4)
+        .map(|i| {
+            client.tx_call(
+                Some(&contract),
+                vec![0; 512],
+                &U256::zero(),
+                nonce + U256::from(i),
+            )
+        })
+        .collect();
+
+    // Only the first two transactions fit into the batch.
+    let size = client
+        .raw_tx(Some(&contract), vec![0; 512], &U256::zero(), nonce)
+        .len();
+    dispatcher.set_size_limits(SizeLimits {
+        max_batch_size: 2 * size,
+        ..Default::default()
+    });
+
+    let results = dispatch(&mut client, &dispatcher, calls);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert_eq!(
+        results[2].as_ref().unwrap_err(),
+        "batch payload limit reached"
+    );
+    assert_eq!(
+        results[3].as_ref().unwrap_err(),
+        "batch payload limit reached"
+    );
+    assert_eq!(client.nonce(&contract), nonce + U256::from(2));
+}
+
+#[test]
+fn test_tx_larger_than_batch() {
+    let mut client = test::Client::new();
+    let mut dispatcher = Dispatcher::new(client.km_client.clone());
+
+    let contract = client.keypair.address();
+    let nonce = client.nonce(&contract);
+    let size = client
+        .raw_tx(Some(&contract), vec![0; 512], &U256::zero(), nonce)
+        .len();
+    dispatcher.set_size_limits(SizeLimits {
+        max_batch_size: size - 1,
+        ..Default::default()
+    });
+
+    // A transaction which can never fit into a batch is rejected permanently.
+    let call = client.tx_call(Some(&contract), vec![0; 512], &U256::zero(), nonce);
+    let results = dispatch(&mut client, &dispatcher, vec![call]);
+    assert!(results[0]
+        .as_ref()
+        .unwrap_err()
+        .starts_with("transaction too large"));
+    assert_eq!(client.nonce(&contract), nonce);
+}
@@ -1,6 +1,7 @@
 extern crate ethereum_types;
 extern crate oasis_ethwasi_runtime;
 extern crate oasis_ethwasi_runtime_api;
+extern crate oasis_ethwasi_runtime_common;
 extern crate time;
 
 mod contracts;
@@ -8,6 +9,7 @@ mod contracts;
 use ethereum_types::{Address, U256};
 use oasis_ethwasi_runtime::test;
 use oasis_ethwasi_runtime_api::ExecutionResult;
+use oasis_ethwasi_runtime_common::header::OasisHeader;
 
 /// Makes a call to the `getCounter()` method.
 fn get_counter<'a>(contract: &Address, client: &mut test::Client) -> U256 {
@@ -58,8 +60,7 @@ fn test_invalid_expiry() {
     let (tx_hash, _) = client.create_contract_with_header(
         contracts::counter::solidity_initcode(),
         &U256::zero(),
-        Some(deploy_expiry),
-        None,
+        OasisHeader::builder().expiry(deploy_expiry).build(),
     );
 
     // check that deploy failed (0 status code)
@@ -80,8 +81,9 @@ fn test_expiry() {
     let (_, contract) = client.create_contract_with_header(
         contracts::counter::solidity_initcode(),
         &U256::zero(),
-        Some(deploy_time + duration),
-        None,
+        OasisHeader::builder()
+            .expiry(deploy_time + duration)
+            .build(),
     );
 
     // check expiry